@@ -0,0 +1,179 @@
+use std::fmt;
+
+/// Which adapter the renderer should pick when it starts up.
+///
+/// Defaults can be overridden with the usual wgpu environment variables
+/// (`WGPU_BACKEND`, `WGPU_POWER_PREF`, `WGPU_ADAPTER_NAME`) and then by the
+/// command line, see [`crate::settings::Settings`].
+#[derive(Debug, Clone)]
+pub struct AdapterConfig {
+    pub backends: wgpu::Backends,
+    pub power_preference: wgpu::PowerPreference,
+    /// Case-insensitive substring the adapter name has to contain.
+    pub name: Option<String>,
+    /// Use a software adapter when no hardware adapter matches.
+    pub allow_fallback: bool,
+    /// Only consider software adapters.
+    pub force_fallback: bool,
+}
+
+impl Default for AdapterConfig {
+    fn default() -> Self {
+        Self {
+            backends: wgpu::Backends::all(),
+            power_preference: wgpu::PowerPreference::LowPower,
+            name: None,
+            allow_fallback: true,
+            force_fallback: false,
+        }
+    }
+}
+
+impl AdapterConfig {
+    pub fn from_env() -> Self {
+        let default = Self::default();
+
+        Self {
+            backends: wgpu::util::backend_bits_from_env().unwrap_or(default.backends),
+            power_preference: wgpu::util::power_preference_from_env()
+                .unwrap_or(default.power_preference),
+            name: std::env::var("WGPU_ADAPTER_NAME").ok(),
+            ..default
+        }
+    }
+
+    pub fn create_instance(&self) -> wgpu::Instance {
+        wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: self.backends,
+            dx12_shader_compiler: wgpu::Dx12Compiler::default(),
+        })
+    }
+
+    /// Enumerates every adapter on the configured backends and picks the best
+    /// match, falling back to a software adapter if allowed.
+    pub async fn select_adapter(
+        &self,
+        instance: &wgpu::Instance,
+        surface: Option<&wgpu::Surface>,
+    ) -> Result<wgpu::Adapter, AdapterError> {
+        let adapters = instance
+            .enumerate_adapters(self.backends)
+            .collect::<Vec<_>>();
+        let available = adapters
+            .iter()
+            .map(wgpu::Adapter::get_info)
+            .collect::<Vec<_>>();
+
+        let (software, hardware): (Vec<_>, Vec<_>) = adapters
+            .into_iter()
+            .filter(|adapter| surface.is_none_or(|s| adapter.is_surface_supported(s)))
+            .filter(|adapter| self.matches_name(&adapter.get_info()))
+            .partition(|adapter| adapter.get_info().device_type == wgpu::DeviceType::Cpu);
+
+        let best = |candidates: Vec<wgpu::Adapter>| {
+            candidates
+                .into_iter()
+                .min_by_key(|adapter| self.rank(&adapter.get_info()))
+        };
+
+        if !self.force_fallback {
+            if let Some(adapter) = best(hardware) {
+                return Ok(adapter);
+            }
+        }
+
+        if self.allow_fallback || self.force_fallback {
+            if let Some(adapter) = best(software) {
+                return Ok(adapter);
+            }
+
+            // Some backends only expose their software adapter through
+            // `force_fallback_adapter`, not through enumeration.
+            if self.name.is_none() {
+                let adapter = instance
+                    .request_adapter(&wgpu::RequestAdapterOptions {
+                        power_preference: self.power_preference,
+                        force_fallback_adapter: true,
+                        compatible_surface: surface,
+                    })
+                    .await;
+
+                if let Some(adapter) = adapter {
+                    return Ok(adapter);
+                }
+            }
+        }
+
+        Err(AdapterError {
+            config: self.clone(),
+            available,
+        })
+    }
+
+    fn matches_name(&self, info: &wgpu::AdapterInfo) -> bool {
+        self.name
+            .as_ref()
+            .is_none_or(|name| info.name.to_lowercase().contains(&name.to_lowercase()))
+    }
+
+    fn rank(&self, info: &wgpu::AdapterInfo) -> (u8, u8) {
+        let device_rank = match (self.power_preference, info.device_type) {
+            (wgpu::PowerPreference::HighPerformance, wgpu::DeviceType::DiscreteGpu) => 0,
+            (wgpu::PowerPreference::HighPerformance, wgpu::DeviceType::IntegratedGpu) => 1,
+            (_, wgpu::DeviceType::IntegratedGpu) => 0,
+            (_, wgpu::DeviceType::DiscreteGpu) => 1,
+            (_, wgpu::DeviceType::VirtualGpu) => 2,
+            (_, wgpu::DeviceType::Other) => 3,
+            (_, wgpu::DeviceType::Cpu) => 4,
+        };
+
+        let backend_rank = match info.backend {
+            wgpu::Backend::Vulkan => 0,
+            wgpu::Backend::Metal | wgpu::Backend::Dx12 => 1,
+            wgpu::Backend::Dx11 => 2,
+            wgpu::Backend::Gl => 3,
+            _ => 4,
+        };
+
+        (device_rank, backend_rank)
+    }
+}
+
+/// No adapter matched the [`AdapterConfig`]. Lists every adapter that was
+/// found so the user can adjust the selection.
+#[derive(Debug)]
+pub struct AdapterError {
+    pub config: AdapterConfig,
+    pub available: Vec<wgpu::AdapterInfo>,
+}
+
+impl fmt::Display for AdapterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "no adapter found for backends {:?}, power preference {:?}",
+            self.config.backends, self.config.power_preference
+        )?;
+
+        if let Some(name) = &self.config.name {
+            write!(f, ", name containing {:?}", name)?;
+        }
+
+        if self.available.is_empty() {
+            return write!(f, "; no adapters are available");
+        }
+
+        write!(f, "; available adapters:")?;
+        for info in &self.available {
+            write!(
+                f,
+                "\n  - {} ({:?}, {:?})",
+                info.name, info.backend, info.device_type
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for AdapterError {}
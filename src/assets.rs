@@ -0,0 +1,205 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::error::{Error, Result};
+
+/// The default scene, built into the binary so it runs from any directory.
+static EMBEDDED: &[(&str, &[u8])] = &[
+    ("Asura.png", include_bytes!("../res/Asura.png")),
+    ("cube.obj", include_bytes!("../res/cube.obj")),
+    ("cube.mtl", include_bytes!("../res/cube.mtl")),
+    (
+        "cube-diffuse.jpg",
+        include_bytes!("../res/cube-diffuse.jpg"),
+    ),
+    ("cube-normal.png", include_bytes!("../res/cube-normal.png")),
+];
+
+/// Somewhere assets can be read from, by a `/` separated path.
+#[derive(Debug, Clone)]
+pub enum AssetSource {
+    /// Files under a directory on disk.
+    Directory(PathBuf),
+    /// Files compiled into the binary with `include_bytes!`.
+    Embedded(&'static [(&'static str, &'static [u8])]),
+    /// Files held in memory, for tests and generated assets.
+    Memory(Arc<HashMap<String, Vec<u8>>>),
+}
+
+impl AssetSource {
+    /// The files of the default scene, built into the binary.
+    pub fn embedded() -> Self {
+        AssetSource::Embedded(EMBEDDED)
+    }
+
+    pub fn memory(files: impl IntoIterator<Item = (String, Vec<u8>)>) -> Self {
+        AssetSource::Memory(Arc::new(files.into_iter().collect()))
+    }
+
+    /// `None` if the source has no such file, so the next one can be tried.
+    fn read(&self, path: &str) -> Option<io::Result<Vec<u8>>> {
+        match self {
+            AssetSource::Directory(root) => match std::fs::read(root.join(path)) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                result => Some(result),
+            },
+            AssetSource::Embedded(files) => files
+                .iter()
+                .find(|(name, _)| *name == path)
+                .map(|(_, bytes)| Ok(bytes.to_vec())),
+            AssetSource::Memory(files) => files.get(path).map(|bytes| Ok(bytes.clone())),
+        }
+    }
+}
+
+/// Search path of asset sources. Every loader reads through this, trying
+/// each source in order and using the first that has the file.
+#[derive(Debug, Clone)]
+pub struct Assets {
+    sources: Vec<AssetSource>,
+}
+
+impl Default for Assets {
+    /// Looks in the directories listed in `HELLO_WGPU_ASSETS`, then `res/`
+    /// under the working directory, next to the executable and in the source
+    /// tree, and finally in the embedded default scene.
+    fn default() -> Self {
+        let mut assets = Self::empty();
+
+        if let Some(paths) = std::env::var_os("HELLO_WGPU_ASSETS") {
+            for path in std::env::split_paths(&paths) {
+                assets.push(AssetSource::Directory(path));
+            }
+        }
+
+        assets.push(AssetSource::Directory(PathBuf::from("res")));
+        if let Some(dir) = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_path_buf))
+        {
+            assets.push(AssetSource::Directory(dir.join("res")));
+        }
+        assets.push(AssetSource::Directory(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("res"),
+        ));
+        assets.push(AssetSource::embedded());
+
+        assets
+    }
+}
+
+impl Assets {
+    /// No sources at all, for building a search path from scratch.
+    pub fn empty() -> Self {
+        Self {
+            sources: Vec::new(),
+        }
+    }
+
+    /// Searched after every source added before it.
+    pub fn push(&mut self, source: AssetSource) {
+        self.sources.push(source);
+    }
+
+    /// Searched before every source added before it.
+    pub fn push_front(&mut self, source: AssetSource) {
+        self.sources.insert(0, source);
+    }
+
+    pub fn sources(&self) -> &[AssetSource] {
+        &self.sources
+    }
+
+    pub fn read(&self, path: &str) -> Result<Vec<u8>> {
+        self.sources
+            .iter()
+            .find_map(|source| source.read(path))
+            .unwrap_or_else(|| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("not found in any of {} asset sources", self.sources.len()),
+                ))
+            })
+            .map_err(|source| Error::MissingAsset {
+                path: path.to_string(),
+                source,
+            })
+    }
+
+    pub fn read_to_string(&self, path: &str) -> Result<String> {
+        let bytes = self.read(path)?;
+        String::from_utf8(bytes).map_err(|e| Error::MissingAsset {
+            path: path.to_string(),
+            source: io::Error::new(io::ErrorKind::InvalidData, e),
+        })
+    }
+}
+
+/// Path of `relative`, as referenced from inside the asset at `from`.
+pub fn resolve(from: &str, relative: &str) -> String {
+    match from.rsplit_once('/') {
+        Some((dir, _)) => format!("{}/{}", dir, relative),
+        None => relative.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory(files: &[(&str, &str)]) -> AssetSource {
+        AssetSource::memory(
+            files
+                .iter()
+                .map(|(path, text)| (path.to_string(), text.as_bytes().to_vec())),
+        )
+    }
+
+    #[test]
+    fn read_uses_the_first_source_with_the_file() {
+        let mut assets = Assets::empty();
+        assets.push(memory(&[("a.txt", "first")]));
+        assets.push(memory(&[("a.txt", "second"), ("b.txt", "only")]));
+
+        assert_eq!(assets.read_to_string("a.txt").unwrap(), "first");
+        assert_eq!(assets.read_to_string("b.txt").unwrap(), "only");
+    }
+
+    #[test]
+    fn push_front_takes_precedence() {
+        let mut assets = Assets::empty();
+        assets.push(memory(&[("a.txt", "pushed")]));
+        assets.push_front(memory(&[("a.txt", "in front")]));
+
+        assert_eq!(assets.read_to_string("a.txt").unwrap(), "in front");
+        assert_eq!(assets.sources().len(), 2);
+    }
+
+    #[test]
+    fn missing_files_report_their_path() {
+        let mut assets = Assets::empty();
+        assets.push(memory(&[("a.txt", "")]));
+
+        match assets.read("models/missing.obj") {
+            Err(Error::MissingAsset { path, source }) => {
+                assert_eq!(path, "models/missing.obj");
+                assert_eq!(source.kind(), io::ErrorKind::NotFound);
+            }
+            other => panic!("expected a missing asset, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn resolve_is_relative_to_the_referencing_asset() {
+        assert_eq!(resolve("cube.obj", "cube.mtl"), "cube.mtl");
+        assert_eq!(resolve("models/cube.obj", "cube.mtl"), "models/cube.mtl");
+        assert_eq!(
+            resolve("models/box/box.gltf", "textures/a.png"),
+            "models/box/textures/a.png"
+        );
+    }
+}
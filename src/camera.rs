@@ -38,7 +38,7 @@ impl CameraInit {
                 label: Some("camera bind group layout"),
                 entries: &[wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
@@ -57,7 +57,7 @@ impl CameraInit {
             }],
         });
 
-        let camera_controller = CameraController::new(0.1);
+        let camera_controller = CameraController::new(6.0);
 
         Self {
             camera,
@@ -70,6 +70,7 @@ impl CameraInit {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
 pub struct Camera {
     pub eye: cgmath::Point3<f32>,
     pub target: cgmath::Point3<f32>,
@@ -81,16 +82,33 @@ pub struct Camera {
 }
 
 impl Camera {
-    fn build_view_proj_matrix(&self) -> cgmath::Matrix4<f32> {
-        let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
+    /// Blends from `previous` to `self` by `alpha`, for rendering between two
+    /// fixed simulation steps.
+    pub fn interpolate(&self, previous: &Camera, alpha: f32) -> Camera {
+        Camera {
+            eye: previous.eye + (self.eye - previous.eye) * alpha,
+            target: previous.target + (self.target - previous.target) * alpha,
+            ..*self
+        }
+    }
 
+    pub fn build_view_matrix(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up)
+    }
+
+    /// View to clip space, with wgpu's 0 to 1 depth range.
+    pub fn build_projection_matrix(&self) -> cgmath::Matrix4<f32> {
         let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
 
-        Self::OPENGL_TO_WGPU_MATRIX * proj * view
+        Self::OPENGL_TO_WGPU_MATRIX * proj
+    }
+
+    fn build_view_proj_matrix(&self) -> cgmath::Matrix4<f32> {
+        self.build_projection_matrix() * self.build_view_matrix()
     }
 
     #[rustfmt::skip]
-    const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
+    pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
         1.0, 0.0, 0.0, 0.0,
         0.0, 1.0, 0.0, 0.0,
         0.0, 0.0, 0.5, 0.0,
@@ -101,17 +119,30 @@ impl Camera {
 #[repr(C)]
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
+    view_position: [f32; 4],
     view_proj: [[f32; 4]; 4],
+    /// World to view space alone, for picking a shadow cascade by depth.
+    view: [[f32; 4]; 4],
+}
+
+impl Default for CameraUniform {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CameraUniform {
     pub fn new() -> Self {
         Self {
+            view_position: [0.0; 4],
             view_proj: cgmath::Matrix4::identity().into(),
+            view: cgmath::Matrix4::identity().into(),
         }
     }
 
     pub fn update_view_proj(&mut self, camera: &Camera) {
+        self.view_position = camera.eye.to_homogeneous().into();
         self.view_proj = camera.build_view_proj_matrix().into();
+        self.view = camera.build_view_matrix().into();
     }
 }
@@ -3,6 +3,7 @@ use cgmath::InnerSpace;
 use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
 
 pub struct CameraController {
+    /// Movement speed in units per second.
     speed: f32,
     is_foward_pressed: bool,
     is_backward_pressed: bool,
@@ -59,17 +60,20 @@ impl CameraController {
         }
     }
 
-    pub fn update_camera(&self, camera: &mut Camera) {
+    /// Moves the camera by `speed * dt`, where `dt` is in seconds.
+    pub fn update_camera(&self, camera: &mut Camera, dt: f32) {
+        let step = self.speed * dt;
+
         let forward = camera.target - camera.eye;
         let forward_norm = forward.normalize();
         let forward_mag = forward.magnitude();
 
-        if self.is_foward_pressed && forward_mag > self.speed {
-            camera.eye += forward_norm * self.speed;
+        if self.is_foward_pressed && forward_mag > step {
+            camera.eye += forward_norm * step;
         }
 
         if self.is_backward_pressed {
-            camera.eye -= forward_norm * self.speed;
+            camera.eye -= forward_norm * step;
         }
 
         let right = forward_norm.cross(camera.up);
@@ -78,11 +82,11 @@ impl CameraController {
         let forward_mag = forward.magnitude();
 
         if self.is_right_pressed {
-            camera.eye = camera.target - (forward + right * self.speed).normalize() * forward_mag;
+            camera.eye = camera.target - (forward + right * step).normalize() * forward_mag;
         }
 
         if self.is_left_pressed {
-            camera.eye = camera.target - (forward - right * self.speed).normalize() * forward_mag;
+            camera.eye = camera.target - (forward - right * step).normalize() * forward_mag;
         }
     }
 }
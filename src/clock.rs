@@ -0,0 +1,151 @@
+use std::time::{Duration, Instant};
+
+/// Timing information for one frame, handed to [`crate::swapchain::State::update`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameTime {
+    /// Time since the previous frame.
+    pub delta: Duration,
+    /// Time since the clock was started.
+    pub elapsed: Duration,
+    /// Index of this frame, starting at 0.
+    pub frame: u64,
+}
+
+pub struct FrameClock {
+    start: Instant,
+    last: Instant,
+    frame: u64,
+}
+
+impl FrameClock {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            start: now,
+            last: now,
+            frame: 0,
+        }
+    }
+
+    pub fn tick(&mut self) -> FrameTime {
+        let now = Instant::now();
+        let time = FrameTime {
+            delta: now - self.last,
+            elapsed: now - self.start,
+            frame: self.frame,
+        };
+
+        self.last = now;
+        self.frame += 1;
+        time
+    }
+}
+
+impl Default for FrameClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Turns variable frame times into a whole number of fixed simulation steps,
+/// keeping the remainder for the next frame and for interpolation.
+pub struct FixedTimestep {
+    step: Duration,
+    accumulator: Duration,
+}
+
+impl FixedTimestep {
+    /// Frames longer than this many steps are clamped, so a long stall does not
+    /// make the simulation try to catch up all at once.
+    const MAX_STEPS_PER_FRAME: u32 = 8;
+
+    /// Panics if `step` is zero.
+    pub fn new(step: Duration) -> Self {
+        assert!(!step.is_zero(), "fixed timestep must be longer than zero");
+        Self {
+            step,
+            accumulator: Duration::ZERO,
+        }
+    }
+
+    /// `None` unless `hz` is a finite, positive rate whose step fits in a
+    /// [`Duration`] and isn't rounded down to zero.
+    pub fn from_rate(hz: f64) -> Option<Self> {
+        if !hz.is_finite() || hz <= 0.0 {
+            return None;
+        }
+        Duration::try_from_secs_f64(1.0 / hz)
+            .ok()
+            .filter(|step| !step.is_zero())
+            .map(Self::new)
+    }
+
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    /// Adds `delta` to the accumulator and returns how many steps to simulate.
+    /// Past [`Self::MAX_STEPS_PER_FRAME`] the rest of the time is dropped.
+    pub fn advance(&mut self, delta: Duration) -> u32 {
+        self.accumulator = self.accumulator.saturating_add(delta);
+
+        let mut steps = 0;
+        while self.accumulator >= self.step {
+            if steps == Self::MAX_STEPS_PER_FRAME {
+                self.accumulator = Duration::ZERO;
+                break;
+            }
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+        steps
+    }
+
+    /// How far the render time is between the previous and the current
+    /// simulation step, in `0.0..1.0`.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.step.as_secs_f32()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn advance_accumulates_partial_steps() {
+        let mut timestep = FixedTimestep::new(10 * MS);
+        assert_eq!(timestep.advance(4 * MS), 0);
+        assert!((timestep.alpha() - 0.4).abs() < 1e-6);
+        assert_eq!(timestep.advance(7 * MS), 1);
+        assert!((timestep.alpha() - 0.1).abs() < 1e-6);
+        assert_eq!(timestep.advance(25 * MS), 2);
+        assert!((timestep.alpha() - 0.6).abs() < 1e-6);
+    }
+
+    #[test]
+    fn long_frames_are_capped() {
+        let mut timestep = FixedTimestep::new(10 * MS);
+        assert_eq!(
+            timestep.advance(Duration::from_secs(3600)),
+            FixedTimestep::MAX_STEPS_PER_FRAME
+        );
+        // The stall isn't caught up on over the following frames.
+        assert_eq!(timestep.alpha(), 0.0);
+        assert_eq!(timestep.advance(5 * MS), 0);
+        assert_eq!(
+            timestep.advance(Duration::MAX),
+            FixedTimestep::MAX_STEPS_PER_FRAME
+        );
+    }
+
+    #[test]
+    fn from_rate_rejects_unusable_rates() {
+        assert_eq!(FixedTimestep::from_rate(50.0).unwrap().step(), 20 * MS);
+        for hz in [0.0, -60.0, f64::NAN, f64::INFINITY, 1e300, 1e-300] {
+            assert!(FixedTimestep::from_rate(hz).is_none(), "accepted {}", hz);
+        }
+    }
+}
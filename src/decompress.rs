@@ -0,0 +1,583 @@
+/// Decodes one mip level of block compressed `format` texels, `width` by
+/// `height` pixels, to tightly packed texels of the returned format. That
+/// is RGBA8, sRGB encoded for sRGB formats and with signed formats remapped
+/// to 0-255, except for BC6H which is HDR and decodes to RGBA16 floats.
+/// `None` for formats without a CPU decoder, which are the HDR ASTC ones.
+pub fn decompress(
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    data: &[u8],
+) -> Option<(wgpu::TextureFormat, Vec<u8>)> {
+    use wgpu::TextureFormat as F;
+
+    let rgba8 = if format.describe().srgb {
+        F::Rgba8UnormSrgb
+    } else {
+        F::Rgba8Unorm
+    };
+
+    let decode_block: fn(&[u8]) -> [[u8; 4]; 16] = match format.remove_srgb_suffix() {
+        F::Bc1RgbaUnorm => |block| rgba(bcdec_rs::bc1, block),
+        F::Bc2RgbaUnorm => |block| rgba(bcdec_rs::bc2, block),
+        F::Bc3RgbaUnorm => |block| rgba(bcdec_rs::bc3, block),
+        F::Bc4RUnorm => |block| red(bc4(block, false)),
+        F::Bc4RSnorm => |block| red(bc4(block, true)),
+        F::Bc5RgUnorm => |block| red_green(bc4(&block[..8], false), bc4(&block[8..], false)),
+        F::Bc5RgSnorm => |block| red_green(bc4(&block[..8], true), bc4(&block[8..], true)),
+        F::Bc6hRgbUfloat => return Some((F::Rgba16Float, bc6h(width, height, data, false))),
+        F::Bc6hRgbSfloat => return Some((F::Rgba16Float, bc6h(width, height, data, true))),
+        F::Bc7RgbaUnorm => |block| rgba(bcdec_rs::bc7, block),
+        F::Etc2Rgb8Unorm => |block| etc2(block, false),
+        F::Etc2Rgb8A1Unorm => |block| etc2(block, true),
+        F::Etc2Rgba8Unorm => etc2_eac,
+        F::EacR11Unorm => |block| red(eac11(block, false)),
+        F::EacR11Snorm => |block| red(eac11(block, true)),
+        F::EacRg11Unorm => |block| red_green(eac11(&block[..8], false), eac11(&block[8..], false)),
+        F::EacRg11Snorm => |block| red_green(eac11(&block[..8], true), eac11(&block[8..], true)),
+        F::Astc {
+            channel: wgpu::AstcChannel::Unorm,
+            ..
+        } => return Some((rgba8, astc(format, width, height, data))),
+        _ => return None,
+    };
+
+    let block_size = format.describe().block_size as usize;
+    let (width, height) = (width as usize, height as usize);
+    let blocks_wide = width.div_ceil(4);
+    let blocks_high = height.div_ceil(4);
+
+    let mut rgba = vec![0; width * height * 4];
+    for (i, block) in data
+        .chunks_exact(block_size)
+        .take(blocks_wide * blocks_high)
+        .enumerate()
+    {
+        let (block_x, block_y) = (i % blocks_wide * 4, i / blocks_wide * 4);
+        for (t, texel) in decode_block(block).iter().enumerate() {
+            let (x, y) = (block_x + t % 4, block_y + t / 4);
+            if x < width && y < height {
+                let offset = (y * width + x) * 4;
+                rgba[offset..offset + 4].copy_from_slice(texel);
+            }
+        }
+    }
+    Some((rgba8, rgba))
+}
+
+/// BC6H blocks as RGBA16 floats with an opaque alpha.
+fn bc6h(width: u32, height: u32, data: &[u8], signed: bool) -> Vec<u8> {
+    const ONE: u16 = 0x3c00;
+
+    let (width, height) = (width as usize, height as usize);
+    let blocks_wide = width.div_ceil(4);
+    let blocks_high = height.div_ceil(4);
+
+    let mut rgba = vec![0; width * height * 8];
+    for (i, block) in data
+        .chunks_exact(16)
+        .take(blocks_wide * blocks_high)
+        .enumerate()
+    {
+        let mut rgb = [0u16; 16 * 3];
+        bcdec_rs::bc6h_half(block, &mut rgb, 4 * 3, signed);
+
+        let (block_x, block_y) = (i % blocks_wide * 4, i / blocks_wide * 4);
+        for (t, texel) in rgb.chunks_exact(3).enumerate() {
+            let (x, y) = (block_x + t % 4, block_y + t / 4);
+            if x < width && y < height {
+                let offset = (y * width + x) * 8;
+                let channels = [texel[0], texel[1], texel[2], ONE];
+                rgba[offset..offset + 8].copy_from_slice(bytemuck::cast_slice(&channels));
+            }
+        }
+    }
+    rgba
+}
+
+/// LDR ASTC blocks of any footprint as RGBA8. Blocks with an invalid
+/// encoding are left transparent black.
+fn astc(format: wgpu::TextureFormat, width: u32, height: u32, data: &[u8]) -> Vec<u8> {
+    let (block_width, block_height) = format.describe().block_dimensions;
+    let footprint = astc_decode::Footprint::new(block_width.into(), block_height.into());
+
+    let mut rgba = vec![0; width as usize * height as usize * 4];
+    // Running out of data leaves the rest of the level black, like the
+    // other decoders do.
+    let _ = astc_decode::astc_decode(data, width, height, footprint, |x, y, texel| {
+        let offset = (y as usize * width as usize + x as usize) * 4;
+        rgba[offset..offset + 4].copy_from_slice(&texel);
+    });
+    rgba
+}
+
+fn red(r: [u8; 16]) -> [[u8; 4]; 16] {
+    r.map(|r| [r, 0, 0, 255])
+}
+
+fn red_green(r: [u8; 16], g: [u8; 16]) -> [[u8; 4]; 16] {
+    std::array::from_fn(|t| [r[t], g[t], 0, 255])
+}
+
+/// The 16 texels of a block, in rows, with their color from `color`.
+fn texels(color: impl Fn(usize, usize) -> [u8; 4]) -> [[u8; 4]; 16] {
+    std::array::from_fn(|t| color(t % 4, t / 4))
+}
+
+fn clamp_u8(value: i32) -> u8 {
+    value.clamp(0, 255) as u8
+}
+
+/// Runs one of bcdec's RGBA8 block decoders on `block`.
+fn rgba(decode: fn(&[u8], &mut [u8], usize), block: &[u8]) -> [[u8; 4]; 16] {
+    let mut texels = [0; 64];
+    decode(block, &mut texels, 4 * 4);
+    bytemuck::cast(texels)
+}
+
+/// One interpolated channel, as used by BC4 and BC5, with signed values
+/// remapped to 0-255.
+fn bc4(block: &[u8], signed: bool) -> [u8; 16] {
+    let mut values = [0; 16];
+    bcdec_rs::bc4(block, &mut values, 4, signed);
+    if signed {
+        values.map(|value| ((value as i8 as i32 + 127) * 255 / 254) as u8)
+    } else {
+        values
+    }
+}
+
+/// Intensity modifiers for each ETC1 table, negated for the other two
+/// indices.
+const ETC_MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+/// Distances between the paint colors of ETC2 T and H mode blocks.
+const ETC_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+/// ETC2 RGB block, which includes ETC1 blocks. In `punchthrough` blocks the
+/// differential bit instead says whether the block is opaque, and when it
+/// isn't, index 2 is transparent black.
+fn etc2(block: &[u8], punchthrough: bool) -> [[u8; 4]; 16] {
+    let bits = u64::from_be_bytes(block[..8].try_into().unwrap());
+    // `count` bits, of which `high` is the most significant.
+    let field = |high: u32, count: u32| ((bits >> (high + 1 - count)) & ((1 << count) - 1)) as i32;
+    // Indices are stored in columns, with their low bits after their high bits.
+    let index = |x: usize, y: usize| {
+        let i = x * 4 + y;
+        ((bits >> (16 + i)) & 1) << 1 | ((bits >> i) & 1)
+    } as usize;
+
+    let flip = field(32, 1) == 1;
+    let (differential, opaque) = if punchthrough {
+        (true, field(33, 1) == 1)
+    } else {
+        (field(33, 1) == 1, true)
+    };
+    let transparent = |i: usize| !opaque && i == 2;
+
+    let extend4 = |v: i32| v << 4 | v;
+    let extend5 = |v: i32| v << 3 | v >> 2;
+    let signed3 = |v: i32| if v >= 4 { v - 8 } else { v };
+    let add = |color: [i32; 3], delta: i32| {
+        [
+            clamp_u8(color[0] + delta),
+            clamp_u8(color[1] + delta),
+            clamp_u8(color[2] + delta),
+            255,
+        ]
+    };
+
+    // Paint colors picked directly by index, for T and H mode blocks.
+    let paint = |colors: [[u8; 4]; 4]| {
+        texels(|x, y| {
+            let i = index(x, y);
+            if transparent(i) {
+                [0; 4]
+            } else {
+                colors[i]
+            }
+        })
+    };
+
+    // ETC1 style individual or differential block, split into two halves
+    // with their own base color and modifier table.
+    let subblocks = |c1: [i32; 3], c2: [i32; 3]| {
+        let tables = [field(39, 3) as usize, field(36, 3) as usize];
+        texels(|x, y| {
+            let half = if flip { y >= 2 } else { x >= 2 } as usize;
+            let i = index(x, y);
+            let [small, large] = ETC_MODIFIERS[tables[half]];
+            let modifier = match i {
+                0 if !opaque => 0,
+                0 => small,
+                1 => large,
+                2 => -small,
+                _ => -large,
+            };
+            if transparent(i) {
+                [0; 4]
+            } else {
+                add([c1, c2][half], modifier)
+            }
+        })
+    };
+
+    if !differential {
+        let c1 = [field(63, 4), field(55, 4), field(47, 4)].map(extend4);
+        let c2 = [field(59, 4), field(51, 4), field(43, 4)].map(extend4);
+        return subblocks(c1, c2);
+    }
+
+    let base = [field(63, 5), field(55, 5), field(47, 5)];
+    let delta = [field(58, 3), field(50, 3), field(42, 3)].map(signed3);
+    let overflows = |c: usize| !(0..32).contains(&(base[c] + delta[c]));
+
+    if overflows(0) {
+        // T mode.
+        let c1 = [field(60, 2) << 2 | field(57, 2), field(55, 4), field(51, 4)].map(extend4);
+        let c2 = [field(47, 4), field(43, 4), field(39, 4)].map(extend4);
+        let d = ETC_DISTANCES[(field(35, 2) << 1 | field(32, 1)) as usize];
+        paint([add(c1, 0), add(c2, d), add(c2, 0), add(c2, -d)])
+    } else if overflows(1) {
+        // H mode. The order of the base colors holds the lowest bit of the
+        // distance.
+        let c1 = [
+            field(62, 4),
+            field(58, 3) << 1 | field(52, 1),
+            field(51, 1) << 3 | field(49, 3),
+        ];
+        let c2 = [field(46, 4), field(42, 4), field(38, 4)];
+        let packed = |c: [i32; 3]| c[0] << 8 | c[1] << 4 | c[2];
+        let order = (packed(c1) >= packed(c2)) as i32;
+        let d = ETC_DISTANCES[(field(34, 1) << 2 | field(32, 1) << 1 | order) as usize];
+        let (c1, c2) = (c1.map(extend4), c2.map(extend4));
+        paint([add(c1, d), add(c1, -d), add(c2, d), add(c2, -d)])
+    } else if overflows(2) {
+        // Planar mode, a gradient from three colors. Always opaque.
+        let extend6 = |v: i32| v << 2 | v >> 4;
+        let extend7 = |v: i32| v << 1 | v >> 6;
+        let o = [
+            extend6(field(62, 6)),
+            extend7(field(56, 1) << 6 | field(54, 6)),
+            extend6(field(48, 1) << 5 | field(44, 2) << 3 | field(41, 3)),
+        ];
+        let h = [
+            extend6(field(38, 5) << 1 | field(32, 1)),
+            extend7(field(31, 7)),
+            extend6(field(24, 6)),
+        ];
+        let v = [
+            extend6(field(18, 6)),
+            extend7(field(12, 7)),
+            extend6(field(5, 6)),
+        ];
+        texels(|x, y| {
+            let (x, y) = (x as i32, y as i32);
+            let channel =
+                |c: usize| clamp_u8((x * (h[c] - o[c]) + y * (v[c] - o[c]) + 4 * o[c] + 2) >> 2);
+            [channel(0), channel(1), channel(2), 255]
+        })
+    } else {
+        let c1 = base.map(extend5);
+        let c2 = [0, 1, 2].map(|c| extend5(base[c] + delta[c]));
+        subblocks(c1, c2)
+    }
+}
+
+#[rustfmt::skip]
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+/// The base, multiplier, modifier table and per texel modifier indices of
+/// an EAC block, with the indices in rows.
+fn eac_fields(block: &[u8]) -> (u8, i32, &'static [i32; 8], [usize; 16]) {
+    let bits = u64::from_be_bytes(block[..8].try_into().unwrap());
+    let indices = std::array::from_fn(|t| {
+        let i = (t % 4) * 4 + t / 4;
+        (bits >> (45 - 3 * i)) as usize & 7
+    });
+    (
+        (bits >> 56) as u8,
+        (bits >> 52) as i32 & 0xf,
+        &EAC_MODIFIERS[(bits >> 48) as usize & 0xf],
+        indices,
+    )
+}
+
+/// ETC2 RGBA: an EAC alpha block followed by an ETC2 RGB block.
+fn etc2_eac(block: &[u8]) -> [[u8; 4]; 16] {
+    let (base, multiplier, modifiers, indices) = eac_fields(&block[..8]);
+    let mut texels = etc2(&block[8..], false);
+    for (texel, index) in texels.iter_mut().zip(indices) {
+        texel[3] = clamp_u8(base as i32 + modifiers[index] * multiplier);
+    }
+    texels
+}
+
+/// An 11 bit EAC channel, as used by R11 and RG11, rounded to 8 bits.
+fn eac11(block: &[u8], signed: bool) -> [u8; 16] {
+    let (base, multiplier, modifiers, indices) = eac_fields(block);
+    // A zero multiplier leaves the modifiers unscaled, at 1/8 the step.
+    let scale = |modifier: i32| match multiplier {
+        0 => modifier,
+        m => modifier * m * 8,
+    };
+    indices.map(|index| {
+        if signed {
+            let base = (base as i8).max(-127) as i32;
+            let value = (base * 8 + scale(modifiers[index])).clamp(-1023, 1023);
+            ((value + 1023) * 255 / 2046) as u8
+        } else {
+            let value = (base as i32 * 8 + 4 + scale(modifiers[index])).clamp(0, 2047);
+            (value * 255 / 2047) as u8
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    #[test]
+    fn bc1_four_color_block() {
+        // Red then blue 565 endpoints, and indices 0-3 along every row.
+        let block = [0x00, 0xf8, 0x1f, 0x00, 0xe4, 0xe4, 0xe4, 0xe4];
+        let texels = rgba(bcdec_rs::bc1, &block);
+        for row in texels.chunks_exact(4) {
+            assert_eq!(row, [RED, BLUE, [170, 0, 85, 255], [85, 0, 170, 255]]);
+        }
+    }
+
+    #[test]
+    fn bc1_three_color_block() {
+        // Black is not above red 16/31, which picks three colors and
+        // transparent black.
+        let block = [0x00, 0x00, 0x00, 0x80, 0xe4, 0xe4, 0xe4, 0xe4];
+        let texels = rgba(bcdec_rs::bc1, &block);
+        for row in texels.chunks_exact(4) {
+            assert_eq!(
+                row,
+                [[0, 0, 0, 255], [132, 0, 0, 255], [66, 0, 0, 255], [0; 4]]
+            );
+        }
+
+        // BC2 and BC3 always decode their color block with four colors.
+        let texels = rgba(bcdec_rs::bc2, &[[0xff; 8].as_slice(), &block].concat());
+        assert_eq!(texels[2], [44, 0, 0, 255]);
+        assert_eq!(texels[3], [88, 0, 0, 255]);
+    }
+
+    #[test]
+    fn bc4_signed_block() {
+        // -128 is clamped to -127, and endpoints in increasing order give
+        // six interpolated values plus -1 and 1. Texels 0-7 use indices 0-7.
+        let block = [0x80, 0x7f, 0x88, 0xc6, 0xfa, 0x00, 0x00, 0x00];
+        let values = bc4(&block, true);
+        assert_eq!(values[..8], [0, 255, 51, 102, 152, 203, 0, 255]);
+        assert_eq!(values[8..], [0; 8]);
+    }
+
+    #[test]
+    fn bc7_mode_6_block() {
+        // Endpoints (255, 1, 1, 255) and (1, 255, 1, 255) after their p-bits,
+        // with index 8 at texel 1 and 15 at texel 15.
+        let block = [
+            0xc0, 0x3f, 0x00, 0xf0, 0x07, 0x00, 0xfe, 0xff, 0x81, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0xf0,
+        ];
+        let texels = rgba(bcdec_rs::bc7, &block);
+        assert_eq!(texels[0], [255, 1, 1, 255]);
+        assert_eq!(texels[1], [120, 136, 1, 255]);
+        assert_eq!(texels[14], [255, 1, 1, 255]);
+        assert_eq!(texels[15], [1, 255, 1, 255]);
+    }
+
+    /// Index `y` for every texel in row `y`, as the high then low index bits.
+    const ETC_ROW_INDICES: [u8; 4] = [0xcc, 0xcc, 0xaa, 0xaa];
+
+    fn rows(texels: [[u8; 4]; 16]) -> Vec<[[u8; 4]; 4]> {
+        texels
+            .chunks_exact(4)
+            .map(|row| row.try_into().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn etc2_individual_block() {
+        // 444 base colors (136, 68, 34) on the left and (255, 0, 0) on the
+        // right, with modifier tables 0 and 7.
+        let block = [&[0x8f, 0x40, 0x20, 0x1c][..], &ETC_ROW_INDICES].concat();
+        let left = [[138, 70, 36], [144, 76, 42], [134, 66, 32], [128, 60, 26]];
+        let right = [[255, 47, 47], [255, 183, 183], [208, 0, 0], [72, 0, 0]];
+        for (y, row) in rows(etc2(&block, false)).into_iter().enumerate() {
+            let [l, r] = [left[y], right[y]].map(|[r, g, b]| [r, g, b, 255]);
+            assert_eq!(row, [l, l, r, r]);
+        }
+    }
+
+    #[test]
+    fn etc2_differential_block() {
+        // 555 base colors (16, 8, 0) and, with a delta of (3, -4, 0),
+        // (19, 4, 0), flipped into top and bottom halves. Every index is 0.
+        let mut block = [0x83, 0x44, 0x00, 0x2f, 0, 0, 0, 0];
+        let texels = etc2(&block, false);
+        assert_eq!(texels[..8], [[137, 71, 5, 255]; 8]);
+        assert_eq!(texels[8..], [[169, 46, 13, 255]; 8]);
+
+        // Without the opaque bit a punchthrough block doesn't modify index
+        // 0, and index 2, here the first texel, is transparent black.
+        block[3] = 0x2d;
+        block[5] = 0x01;
+        let texels = etc2(&block, true);
+        assert_eq!(texels[0], [0; 4]);
+        assert_eq!(texels[1..8], [[132, 66, 0, 255]; 7]);
+        assert_eq!(texels[8..], [[156, 33, 0, 255]; 8]);
+    }
+
+    #[test]
+    fn etc2_t_mode_block() {
+        // Red overflows. Base colors 0x6c3 and 0x28a, distance 32, and
+        // index `x` for every texel in column `x`.
+        let block = [0x0e, 0xc3, 0x28, 0xab, 0xff, 0x00, 0xf0, 0xf0];
+        for row in rows(etc2(&block, false)) {
+            assert_eq!(
+                row,
+                [
+                    [102, 204, 51, 255],
+                    [66, 168, 202, 255],
+                    [34, 136, 170, 255],
+                    [2, 104, 138, 255],
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn etc2_h_mode_block() {
+        // Green overflows. Base colors 0xa45 and 0x369, where the first
+        // being larger makes the distance index 5, for 32.
+        let block = [&[0x52, 0x06, 0x9b, 0x4e][..], &ETC_ROW_INDICES].concat();
+        let paint = [
+            [202, 100, 117],
+            [138, 36, 53],
+            [83, 134, 185],
+            [19, 70, 121],
+        ];
+        for (y, row) in rows(etc2(&block, false)).into_iter().enumerate() {
+            let [r, g, b] = paint[y];
+            assert_eq!(row, [[r, g, b, 255]; 4]);
+        }
+    }
+
+    #[test]
+    fn etc2_planar_block() {
+        // Blue overflows. Origin (16, 64, 32), horizontal (63, 0, 63) and
+        // vertical (0, 127, 0) in 676 bits, or (65, 129, 130), (255, 0, 255)
+        // and (0, 255, 0) widened.
+        let block = [0x21, 0x01, 0x04, 0x7f, 0x01, 0xf8, 0x1f, 0xc0];
+        let texels = etc2(&block, false);
+        assert_eq!(texels[0], [65, 129, 130, 255]);
+        assert_eq!(texels[3], [208, 32, 224, 255]);
+        assert_eq!(texels[9], [80, 160, 96, 255]);
+        assert_eq!(texels[12], [16, 224, 33, 255]);
+        assert_eq!(texels[15], [159, 127, 126, 255]);
+    }
+
+    /// EAC indices 0-7 then 0-7 again, down the columns.
+    const EAC_INDICES: [u8; 6] = [0x05, 0x39, 0x77, 0x05, 0x39, 0x77];
+
+    #[test]
+    fn etc2_eac_alpha_block() {
+        // Base 240, multiplier 3 and modifier table 0, clamped at 255. The
+        // color block is all zeros, for gray 2.
+        let block = [&[0xf0, 0x30][..], &EAC_INDICES, &[0; 8]].concat();
+        let alpha = [231, 222, 213, 195, 246, 255, 255, 255];
+        for (y, row) in rows(etc2_eac(&block)).into_iter().enumerate() {
+            let [a, b] = [alpha[y], alpha[4 + y]];
+            assert_eq!(
+                row,
+                [[2, 2, 2, a], [2, 2, 2, b], [2, 2, 2, a], [2, 2, 2, b]]
+            );
+        }
+    }
+
+    #[test]
+    fn eac_r11_unsigned_block() {
+        // Base 100, multiplier 2 and modifier table 13, which is 804 plus
+        // 16 times the modifier in 11 bits.
+        let mut block = [&[0x64, 0x2d][..], &EAC_INDICES].concat();
+        let values = [98, 96, 94, 80, 100, 102, 104, 118];
+        for (y, row) in eac11(&block, false).chunks_exact(4).enumerate() {
+            assert_eq!(row, [values[y], values[4 + y], values[y], values[4 + y]]);
+        }
+
+        // A zero multiplier adds the modifier unscaled.
+        block[1] = 0x0d;
+        let values = eac11(&block, false);
+        assert_eq!([values[12], values[13]], [98, 101]);
+    }
+
+    #[test]
+    fn eac_r11_signed_block() {
+        // Base -100, multiplier 4 and modifier table 0, which is -800 plus
+        // 32 times the modifier, clamped at -1023.
+        let block = [&[0x9c, 0x40][..], &EAC_INDICES].concat();
+        let values = [15, 3, 0, 0, 35, 47, 59, 83];
+        for (y, row) in eac11(&block, true).chunks_exact(4).enumerate() {
+            assert_eq!(row, [values[y], values[4 + y], values[y], values[4 + y]]);
+        }
+    }
+
+    #[test]
+    fn astc_void_extent_block() {
+        // A constant color block: the void extent marker, then 16 bit RGBA.
+        let mut block = 0xffff_ffff_ffff_fdfc_u64.to_le_bytes().to_vec();
+        for channel in [0x4000_u16, 0x8000, 0xc000, 0xffff] {
+            block.extend(channel.to_le_bytes());
+        }
+        let format = wgpu::TextureFormat::Astc {
+            block: wgpu::AstcBlock::B4x4,
+            channel: wgpu::AstcChannel::UnormSrgb,
+        };
+        let (format, rgba) = decompress(format, 3, 2, &block).unwrap();
+        assert_eq!(format, wgpu::TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(rgba, [0x40, 0x80, 0xc0, 0xff].repeat(6));
+    }
+
+    #[test]
+    fn bc6h_decodes_to_half_floats() {
+        let format = wgpu::TextureFormat::Bc6hRgbUfloat;
+        let (format, rgba) = decompress(format, 2, 2, &[0; 16]).unwrap();
+        assert_eq!(format, wgpu::TextureFormat::Rgba16Float);
+        let one = half::f16::ONE.to_le_bytes();
+        assert_eq!(rgba, [[0, 0, 0, 0, 0, 0, one[0], one[1]]; 4].concat());
+    }
+}
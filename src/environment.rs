@@ -0,0 +1,285 @@
+use std::f32::consts::PI;
+
+use cgmath::{InnerSpace, Vector3};
+use image::{DynamicImage, Rgba32FImage};
+
+use crate::{
+    assets::Assets,
+    error::{Error, Result},
+    mipmap,
+};
+
+/// Reads a Radiance `.hdr`, OpenEXR or any other image `image` can decode
+/// as linear floating point colors. Integer images, such as 8 bit PNGs and
+/// JPEGs, are taken to be sRGB encoded and have their colors decoded.
+pub fn load_hdr_image(assets: &Assets, file: &str) -> Result<Rgba32FImage> {
+    let bytes = assets.read(file)?;
+    let image = image::load_from_memory(&bytes).map_err(|source| Error::Decode {
+        path: file.to_string(),
+        source,
+    })?;
+
+    let srgb = !matches!(
+        image,
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    );
+    let mut image = image.to_rgba32f();
+    if srgb {
+        for pixel in image.pixels_mut() {
+            for value in &mut pixel.0[..3] {
+                *value = mipmap::srgb_to_linear(*value);
+            }
+        }
+    }
+    Ok(image)
+}
+
+/// Loads an equirectangular panorama as six cube faces, a quarter of its
+/// width so the panorama's resolution is kept.
+pub fn load_equirect(assets: &Assets, file: &str) -> Result<[Rgba32FImage; 6]> {
+    let panorama = load_hdr_image(assets, file)?;
+    Ok(equirect_to_cube(&panorama, (panorama.width() / 4).max(1)))
+}
+
+/// Loads six square images of the same size, in +X, -X, +Y, -Y, +Z, -Z
+/// order, as cube faces.
+pub fn load_faces(assets: &Assets, files: &[String; 6]) -> Result<[Rgba32FImage; 6]> {
+    let mut faces = Vec::with_capacity(6);
+    for file in files {
+        faces.push(load_hdr_image(assets, file)?);
+    }
+
+    let size = faces[0].width();
+    for (face, file) in faces.iter().zip(files) {
+        if face.dimensions() != (size, size) {
+            return Err(Error::CubeFaceSize {
+                path: file.clone(),
+                size: face.dimensions(),
+                expected: size,
+            });
+        }
+    }
+
+    Ok(faces.try_into().unwrap())
+}
+
+/// The float format environment cube maps are stored in. Full floats where
+/// the adapter can filter them, half floats otherwise.
+pub fn cube_format(adapter: &wgpu::Adapter, device: &wgpu::Device) -> wgpu::TextureFormat {
+    let format = wgpu::TextureFormat::Rgba32Float;
+    let filterable = device
+        .features()
+        .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+        && adapter
+            .get_texture_format_features(format)
+            .flags
+            .contains(wgpu::TextureFormatFeatureFlags::FILTERABLE);
+
+    if filterable {
+        format
+    } else {
+        wgpu::TextureFormat::Rgba16Float
+    }
+}
+
+/// Resamples an equirectangular panorama into six `size` square cube faces,
+/// in +X, -X, +Y, -Y, +Z, -Z order. The middle of the panorama faces -Z and
+/// its top row is straight up.
+pub fn equirect_to_cube(panorama: &Rgba32FImage, size: u32) -> [Rgba32FImage; 6] {
+    std::array::from_fn(|face| {
+        Rgba32FImage::from_fn(size, size, |x, y| {
+            let u = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+            let v = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+            let (s, t) = direction_to_equirect(cube_face_direction(face, u, v));
+            image::Rgba(sample_bilinear(panorama, s, t))
+        })
+    })
+}
+
+/// Direction through a point on cube face `face`, with `u` running left to
+/// right and `v` top to bottom across the face, both from -1 to 1. Matches
+/// how the GPU picks a face and texel when sampling a cube map.
+pub fn cube_face_direction(face: usize, u: f32, v: f32) -> Vector3<f32> {
+    let direction = match face {
+        0 => Vector3::new(1.0, -v, -u),
+        1 => Vector3::new(-1.0, -v, u),
+        2 => Vector3::new(u, 1.0, v),
+        3 => Vector3::new(u, -1.0, -v),
+        4 => Vector3::new(u, -v, 1.0),
+        5 => Vector3::new(-u, -v, -1.0),
+        _ => panic!("cube face {} out of range", face),
+    };
+    direction.normalize()
+}
+
+/// The cube face `direction` points through and where on it, the inverse
+/// of [`cube_face_direction`].
+pub fn direction_to_cube(direction: Vector3<f32>) -> (usize, f32, f32) {
+    let Vector3 { x, y, z } = direction;
+    let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+    if ax >= ay && ax >= az {
+        if x > 0.0 {
+            (0, -z / ax, -y / ax)
+        } else {
+            (1, z / ax, -y / ax)
+        }
+    } else if ay >= az {
+        if y > 0.0 {
+            (2, x / ay, z / ay)
+        } else {
+            (3, x / ay, -z / ay)
+        }
+    } else if z > 0.0 {
+        (4, x / az, -y / az)
+    } else {
+        (5, -x / az, -y / az)
+    }
+}
+
+/// Bilinear sample of a cube map's `faces` in `direction`. Samples don't
+/// blend across face edges, which only shows at very low resolutions.
+pub fn sample_cube(faces: &[Rgba32FImage; 6], direction: Vector3<f32>) -> [f32; 4] {
+    let (face, u, v) = direction_to_cube(direction);
+    let image = &faces[face];
+    let size = image.width() as f32;
+    let x = ((u + 1.0) * 0.5 * size - 0.5).clamp(0.0, size - 1.0);
+    let y = ((v + 1.0) * 0.5 * size - 0.5).clamp(0.0, size - 1.0);
+    bilinear(image, x, y, |x| x.clamp(0, image.width() as i64 - 1) as u32)
+}
+
+/// Where `direction` lands on an equirectangular panorama, as texture
+/// coordinates from 0 to 1.
+pub fn direction_to_equirect(direction: Vector3<f32>) -> (f32, f32) {
+    let direction = direction.normalize();
+    let longitude = direction.x.atan2(-direction.z);
+    let latitude = direction.y.clamp(-1.0, 1.0).acos();
+    (0.5 + longitude / (2.0 * PI), latitude / PI)
+}
+
+/// Bilinear sample of `image` at texture coordinates `s`, `t`, wrapping
+/// around horizontally and clamping at the poles.
+fn sample_bilinear(image: &Rgba32FImage, s: f32, t: f32) -> [f32; 4] {
+    let (width, height) = image.dimensions();
+    let x = s * width as f32 - 0.5;
+    let y = (t * height as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+    bilinear(image, x, y, |x| x.rem_euclid(width as i64) as u32)
+}
+
+/// Blends the four texels around `x`, `y`, in texels. `wrap_x` brings
+/// columns outside the image back into it; rows are clamped.
+fn bilinear(image: &Rgba32FImage, x: f32, y: f32, wrap_x: impl Fn(i64) -> u32) -> [f32; 4] {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    let texel = |x: f32, y: f32| {
+        let x = wrap_x(x as i64);
+        let y = (y as u32).min(image.height() - 1);
+        image.get_pixel(x, y).0
+    };
+    let (a, b) = (texel(x0, y0), texel(x0 + 1.0, y0));
+    let (c, d) = (texel(x0, y0 + 1.0), texel(x0 + 1.0, y0 + 1.0));
+
+    std::array::from_fn(|channel| {
+        let top = a[channel] + (b[channel] - a[channel]) * fx;
+        let bottom = c[channel] + (d[channel] - c[channel]) * fx;
+        top + (bottom - top) * fy
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::AssetSource;
+
+    fn assets_with(file: &str, image: DynamicImage, format: image::ImageOutputFormat) -> Assets {
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut bytes, format).unwrap();
+        let mut assets = Assets::empty();
+        assets.push(AssetSource::memory([(
+            file.to_string(),
+            bytes.into_inner(),
+        )]));
+        assets
+    }
+
+    #[test]
+    fn eight_bit_images_are_decoded_from_srgb() {
+        let image = image::RgbaImage::from_pixel(1, 1, image::Rgba([0, 128, 255, 128]));
+        let assets = assets_with(
+            "sky.png",
+            DynamicImage::ImageRgba8(image),
+            image::ImageOutputFormat::Png,
+        );
+
+        let [r, g, b, a] = load_hdr_image(&assets, "sky.png")
+            .unwrap()
+            .get_pixel(0, 0)
+            .0;
+        assert_eq!((r, b), (0.0, 1.0));
+        assert!((g - 0.2158).abs() < 1e-3, "green {}", g);
+        assert!((a - 128.0 / 255.0).abs() < 1e-6, "alpha {}", a);
+    }
+
+    #[test]
+    fn float_images_stay_linear() {
+        let image = Rgba32FImage::from_pixel(1, 1, image::Rgba([0.5, 2.0, 0.25, 1.0]));
+        let assets = assets_with(
+            "sky.exr",
+            DynamicImage::ImageRgba32F(image),
+            image::ImageOutputFormat::OpenExr,
+        );
+
+        let pixel = load_hdr_image(&assets, "sky.exr")
+            .unwrap()
+            .get_pixel(0, 0)
+            .0;
+        assert_eq!(pixel, [0.5, 2.0, 0.25, 1.0]);
+    }
+
+    #[test]
+    fn cube_face_coordinates_round_trip() {
+        let steps = [-0.9, -0.5, 0.0, 0.3, 0.9];
+        for face in 0..6 {
+            for u in steps {
+                for v in steps {
+                    let direction = cube_face_direction(face, u, v);
+                    let (back, back_u, back_v) = direction_to_cube(direction);
+                    assert_eq!(back, face, "face of {:?}", direction);
+                    assert!(
+                        (back_u - u).abs() < 1e-5 && (back_v - v).abs() < 1e-5,
+                        "face {} ({}, {}) came back as ({}, {})",
+                        face,
+                        u,
+                        v,
+                        back_u,
+                        back_v
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn panorama_texels_land_on_their_cube_faces() {
+        // Each texel holds the face it should land on: the top and bottom
+        // rows are up and down, and the middle rows go -X, -Z, +X and +Z
+        // in quarters from the left, offset by one column so each face
+        // center falls between two columns of the same face.
+        let panorama = Rgba32FImage::from_fn(8, 4, |x, y| {
+            let face = match (y, (x + 1) % 8 / 2) {
+                (0, _) => 2,
+                (3, _) => 3,
+                (_, 0) => 4,
+                (_, 1) => 1,
+                (_, 2) => 5,
+                _ => 0,
+            };
+            image::Rgba([face as f32, 0.0, 0.0, 1.0])
+        });
+
+        let faces = equirect_to_cube(&panorama, 1);
+        for (face, image) in faces.iter().enumerate() {
+            assert_eq!(image.get_pixel(0, 0).0, [face as f32, 0.0, 0.0, 1.0]);
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt;
+
+use crate::adapter::AdapterError;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Everything that can go wrong while setting up the renderer or loading assets.
+#[derive(Debug)]
+pub enum Error {
+    AdapterNotFound(AdapterError),
+    CreateSurface(wgpu::CreateSurfaceError),
+    RequestDevice(wgpu::RequestDeviceError),
+    /// The surface reported no formats the renderer can draw into.
+    UnsupportedSurfaceFormat(Vec<wgpu::TextureFormat>),
+    MissingAsset {
+        path: String,
+        source: std::io::Error,
+    },
+    Decode {
+        path: String,
+        source: image::ImageError,
+    },
+    ObjParse {
+        path: String,
+        source: tobj::LoadError,
+    },
+    GltfImport {
+        path: String,
+        source: gltf::Error,
+    },
+    /// A model file that parsed, but refers to data it doesn't have.
+    Model {
+        path: String,
+        reason: String,
+    },
+    /// A KTX2 or DDS file that is malformed, or holds a texture that can't
+    /// be used.
+    TextureContainer {
+        path: String,
+        reason: String,
+    },
+    /// A cube map face that isn't square, or not the size of the first face.
+    CubeFaceSize {
+        path: String,
+        size: (u32, u32),
+        expected: u32,
+    },
+    /// Frames can only be read back from a headless [`crate::swapchain::State`].
+    NoOffscreenTarget,
+    FrameReadback(wgpu::BufferAsyncError),
+    /// The read back pixels don't fill a `width` x `height` image.
+    FrameSize {
+        width: u32,
+        height: u32,
+        len: usize,
+    },
+    Encode {
+        path: String,
+        source: image::ImageError,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::AdapterNotFound(e) => write!(f, "{}", e),
+            Error::CreateSurface(e) => write!(f, "failed to create surface: {}", e),
+            Error::RequestDevice(e) => write!(f, "failed to request device: {}", e),
+            Error::UnsupportedSurfaceFormat(formats) => {
+                write!(
+                    f,
+                    "surface supports no usable format (offered {:?})",
+                    formats
+                )
+            }
+            Error::MissingAsset { path, source } => {
+                write!(f, "failed to read asset {}: {}", path, source)
+            }
+            Error::Decode { path, source } => write!(f, "failed to decode {}: {}", path, source),
+            Error::ObjParse { path, source } => write!(f, "failed to load {}: {}", path, source),
+            Error::GltfImport { path, source } => {
+                write!(f, "failed to load {}: {}", path, source)
+            }
+            Error::Model { path, reason } => write!(f, "failed to load {}: {}", path, reason),
+            Error::TextureContainer { path, reason } => {
+                write!(f, "failed to load texture {}: {}", path, reason)
+            }
+            Error::CubeFaceSize {
+                path,
+                size,
+                expected,
+            } => write!(
+                f,
+                "cube face {} is {}x{}, expected {}x{}",
+                path, size.0, size.1, expected, expected
+            ),
+            Error::NoOffscreenTarget => write!(f, "only headless states can read frames back"),
+            Error::FrameReadback(e) => write!(f, "failed to read frame back: {}", e),
+            Error::FrameSize { width, height, len } => write!(
+                f,
+                "read back {} bytes for a {}x{} frame",
+                len, width, height
+            ),
+            Error::Encode { path, source } => write!(f, "failed to write {}: {}", path, source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::AdapterNotFound(e) => Some(e),
+            Error::CreateSurface(e) => Some(e),
+            Error::RequestDevice(e) => Some(e),
+            Error::MissingAsset { source, .. } => Some(source),
+            Error::Decode { source, .. } | Error::Encode { source, .. } => Some(source),
+            Error::ObjParse { source, .. } => Some(source),
+            Error::GltfImport { source, .. } => Some(source),
+            Error::FrameReadback(e) => Some(e),
+            Error::UnsupportedSurfaceFormat(_)
+            | Error::Model { .. }
+            | Error::TextureContainer { .. }
+            | Error::CubeFaceSize { .. }
+            | Error::NoOffscreenTarget
+            | Error::FrameSize { .. } => None,
+        }
+    }
+}
+
+impl From<AdapterError> for Error {
+    fn from(e: AdapterError) -> Self {
+        Error::AdapterNotFound(e)
+    }
+}
+
+impl From<wgpu::CreateSurfaceError> for Error {
+    fn from(e: wgpu::CreateSurfaceError) -> Self {
+        Error::CreateSurface(e)
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for Error {
+    fn from(e: wgpu::RequestDeviceError) -> Self {
+        Error::RequestDevice(e)
+    }
+}
+
+impl From<wgpu::BufferAsyncError> for Error {
+    fn from(e: wgpu::BufferAsyncError) -> Self {
+        Error::FrameReadback(e)
+    }
+}
@@ -0,0 +1,542 @@
+use std::sync::Arc;
+
+use cgmath::{InnerSpace, Matrix, SquareMatrix};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    assets::{self, Assets},
+    error::{Error, Result},
+    model::{self, Material, MaterialTextures, MaterialUniform, Mesh, Model, ModelVertex},
+    texture::{self, SamplerOptions, TextureKind},
+    texture_cache::{TextureCache, TextureKey},
+};
+
+/// Loads a `.gltf` (with external or embedded buffers) or binary `.glb` file
+/// from `assets`. Every primitive of every mesh node in the default scene
+/// becomes one [`Mesh`], with the node's world transform baked into its
+/// vertices.
+pub fn load(
+    file_name: &str,
+    textures: &mut TextureCache,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+) -> Result<Model> {
+    let gltf_error = |source| Error::GltfImport {
+        path: file_name.to_string(),
+        source,
+    };
+
+    let assets = textures.assets();
+    let gltf::Gltf { document, blob } =
+        gltf::Gltf::from_slice(&assets.read(file_name)?).map_err(gltf_error)?;
+    let buffers = load_buffers(&document, blob, file_name, assets)?;
+
+    let mut materials = document
+        .materials()
+        .map(|m| load_material(&m, &buffers, file_name, textures, device, queue, layout))
+        .collect::<Result<Vec<_>>>()?;
+    // Primitives without a material use glTF's default: white, fully rough
+    // and non-metallic. Only added when something needs it.
+    let mut default_material = None;
+
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next());
+
+    let mut meshes = Vec::new();
+    for node in scene.iter().flat_map(|scene| scene.nodes()) {
+        let mut stack = vec![(node, cgmath::Matrix4::identity())];
+        while let Some((node, parent)) = stack.pop() {
+            let transform = parent * cgmath::Matrix4::from(node.transform().matrix());
+            stack.extend(node.children().map(|child| (child, transform)));
+
+            let Some(mesh) = node.mesh() else {
+                continue;
+            };
+
+            for primitive in mesh.primitives() {
+                let material = match (primitive.material().index(), default_material) {
+                    (Some(index), _) | (None, Some(index)) => index,
+                    (None, None) => {
+                        let material = primitive.material();
+                        materials.push(load_material(
+                            &material, &buffers, file_name, textures, device, queue, layout,
+                        )?);
+                        default_material = Some(materials.len() - 1);
+                        materials.len() - 1
+                    }
+                };
+
+                let name = mesh.name().unwrap_or(file_name);
+                if let Some(mesh) = load_primitive(
+                    &primitive, &buffers, transform, file_name, name, material, device,
+                )? {
+                    meshes.push(mesh);
+                }
+            }
+        }
+    }
+
+    Ok(Model { meshes, materials })
+}
+
+/// Builds the GPU buffers for one primitive of mesh `name` in `file_name`,
+/// or `None` for point and line primitives and ones without positions.
+fn load_primitive(
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+    transform: cgmath::Matrix4<f32>,
+    file_name: &str,
+    name: &str,
+    material: usize,
+    device: &wgpu::Device,
+) -> Result<Option<Mesh>> {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+    let Some(positions) = reader.read_positions() else {
+        log::warn!("skipping primitive of {:?} without positions", name);
+        return Ok(None);
+    };
+
+    let mut vertices = positions
+        .map(|position| ModelVertex {
+            position,
+            tex_coords: [0.0; 2],
+            normal: [0.0; 3],
+            tangent: [0.0; 3],
+            bitangent: [0.0; 3],
+            color: [1.0; 3],
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(tex_coords) = reader.read_tex_coords(0) {
+        for (vertex, tex_coords) in vertices.iter_mut().zip(tex_coords.into_f32()) {
+            vertex.tex_coords = tex_coords;
+        }
+    }
+
+    if let Some(colors) = reader.read_colors(0) {
+        for (vertex, color) in vertices.iter_mut().zip(colors.into_rgb_f32()) {
+            vertex.color = color;
+        }
+    }
+
+    let indices = reader
+        .read_indices()
+        .map(|indices| indices.into_u32().collect::<Vec<_>>())
+        .unwrap_or_else(|| (0..vertices.len() as u32).collect());
+    if let Some(index) = indices
+        .iter()
+        .find(|&&index| index as usize >= vertices.len())
+    {
+        return Err(Error::Model {
+            path: file_name.to_string(),
+            reason: format!(
+                "primitive of {:?} has index {} but {} vertices",
+                name,
+                index,
+                vertices.len()
+            ),
+        });
+    }
+
+    let mut indices = match primitive.mode() {
+        gltf::mesh::Mode::Triangles => indices,
+        gltf::mesh::Mode::TriangleStrip => (2..indices.len())
+            .flat_map(|i| {
+                // Every other triangle of a strip is wound the other way.
+                if i % 2 == 0 {
+                    [indices[i - 2], indices[i - 1], indices[i]]
+                } else {
+                    [indices[i - 1], indices[i - 2], indices[i]]
+                }
+            })
+            .collect(),
+        gltf::mesh::Mode::TriangleFan => (2..indices.len())
+            .flat_map(|i| [indices[0], indices[i - 1], indices[i]])
+            .collect(),
+        mode => {
+            log::warn!("skipping {:?} primitive of {:?}", mode, name);
+            return Ok(None);
+        }
+    };
+
+    // The spec asks for flat shading when normals are missing, and any
+    // tangents that came with them would no longer line up once the
+    // vertices are split per face.
+    let tangents = match reader.read_normals() {
+        Some(normals) => {
+            for (vertex, normal) in vertices.iter_mut().zip(normals) {
+                vertex.normal = normal;
+            }
+            reader.read_tangents()
+        }
+        None => {
+            (vertices, indices) = model::generate_normals(&vertices, &indices, 0.0);
+            None
+        }
+    };
+
+    match tangents {
+        Some(tangents) => {
+            for (vertex, tangent) in vertices.iter_mut().zip(tangents) {
+                let normal = cgmath::Vector3::from(vertex.normal);
+                let xyz = cgmath::Vector3::new(tangent[0], tangent[1], tangent[2]);
+                vertex.tangent = xyz.into();
+                vertex.bitangent = (normal.cross(xyz) * tangent[3]).into();
+            }
+        }
+        None => model::compute_tangents(&mut vertices, &indices),
+    }
+
+    // Normals transform by the inverse transpose to stay perpendicular under
+    // non-uniform scale.
+    let linear = cgmath::Matrix3::from_cols(
+        transform.x.truncate(),
+        transform.y.truncate(),
+        transform.z.truncate(),
+    );
+    let normal_matrix = linear
+        .invert()
+        .map(|inverse| inverse.transpose())
+        .unwrap_or(linear);
+
+    for vertex in &mut vertices {
+        let position = transform * cgmath::Vector3::from(vertex.position).extend(1.0);
+        vertex.position = position.truncate().into();
+        vertex.normal = (normal_matrix * cgmath::Vector3::from(vertex.normal))
+            .normalize()
+            .into();
+        vertex.tangent = (linear * cgmath::Vector3::from(vertex.tangent)).into();
+        vertex.bitangent = (linear * cgmath::Vector3::from(vertex.bitangent)).into();
+    }
+
+    // A mirroring transform turns front faces into back faces.
+    if linear.determinant() < 0.0 {
+        for triangle in indices.chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+        }
+    }
+
+    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{:?} Vertex Buffer", name)),
+        contents: bytemuck::cast_slice(&vertices),
+        usage: wgpu::BufferUsages::VERTEX,
+    });
+    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{:?} Index Buffer", name)),
+        contents: bytemuck::cast_slice(&indices),
+        usage: wgpu::BufferUsages::INDEX,
+    });
+
+    Ok(Some(Mesh {
+        name: name.to_string(),
+        vertex_buffer,
+        index_buffer,
+        num_elements: indices.len() as u32,
+        material,
+    }))
+}
+
+/// Reads every buffer, from the GLB binary chunk, a `data:` URI or a file
+/// next to the glTF file.
+fn load_buffers(
+    document: &gltf::Document,
+    mut blob: Option<Vec<u8>>,
+    file_name: &str,
+    assets: &Assets,
+) -> Result<Vec<gltf::buffer::Data>> {
+    let gltf_error = |source| Error::GltfImport {
+        path: file_name.to_string(),
+        source,
+    };
+
+    document
+        .buffers()
+        .map(|buffer| {
+            let data = match buffer.source() {
+                gltf::buffer::Source::Uri(uri) if !uri.starts_with("data:") => {
+                    let mut bytes =
+                        assets.read(&assets::resolve(file_name, &percent_decode(uri)))?;
+                    // Accessors are read in four byte units, like gltf::import pads.
+                    bytes.resize(bytes.len().next_multiple_of(4), 0);
+                    gltf::buffer::Data(bytes)
+                }
+                source => gltf::buffer::Data::from_source_and_blob(source, None, &mut blob)
+                    .map_err(gltf_error)?,
+            };
+
+            if data.len() < buffer.length() {
+                return Err(gltf_error(gltf::Error::BufferLength {
+                    buffer: buffer.index(),
+                    expected: buffer.length(),
+                    actual: data.len(),
+                }));
+            }
+            Ok(data)
+        })
+        .collect()
+}
+
+/// Decodes an image from a buffer view, a `data:` URI or a file next to the
+/// glTF file, or `None` with a warning if its pixel format can't be uploaded.
+fn decode_image(
+    image: &gltf::Image,
+    buffers: &[gltf::buffer::Data],
+    file_name: &str,
+    assets: &Assets,
+) -> Result<Option<image::DynamicImage>> {
+    let encoded = match image.source() {
+        gltf::image::Source::View { view, .. } => {
+            let buffer = &buffers[view.buffer().index()];
+            buffer
+                .get(view.offset()..view.offset() + view.length())
+                .ok_or_else(|| Error::Model {
+                    path: file_name.to_string(),
+                    reason: format!(
+                        "image {} reads past the end of buffer {}",
+                        image.index(),
+                        view.buffer().index()
+                    ),
+                })?
+                .to_vec()
+        }
+        gltf::image::Source::Uri { uri, .. } if !uri.starts_with("data:") => {
+            assets.read(&assets::resolve(file_name, &percent_decode(uri)))?
+        }
+        source => {
+            // gltf only decodes `data:` URIs when given a base path, which
+            // they never use.
+            let data =
+                gltf::image::Data::from_source(source, Some(std::path::Path::new("")), buffers)
+                    .map_err(|source| Error::GltfImport {
+                        path: file_name.to_string(),
+                        source,
+                    })?;
+            let image = to_dynamic_image(&data);
+            if image.is_none() {
+                log::warn!("unsupported {:?} image in {:?}", data.format, file_name);
+            }
+            return Ok(image);
+        }
+    };
+
+    image::load_from_memory(&encoded)
+        .map(Some)
+        .map_err(|source| Error::Decode {
+            path: format!("{} image {}", file_name, image.index()),
+            source,
+        })
+}
+
+/// Undoes the `%XX` escapes glTF exporters use for spaces and other
+/// characters in URIs.
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn load_material(
+    material: &gltf::Material,
+    buffers: &[gltf::buffer::Data],
+    file_name: &str,
+    textures: &mut TextureCache,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+) -> Result<Material> {
+    let name = material.name().unwrap_or("glTF material");
+    let pbr = material.pbr_metallic_roughness();
+    let plain = MaterialTextures::plain(textures, device, queue);
+    let mut load = |info: Option<gltf::Texture>, kind, fallback: &Arc<texture::Texture>| {
+        let texture = match info {
+            Some(texture) => {
+                load_texture(&texture, buffers, file_name, kind, textures, device, queue)?
+            }
+            None => None,
+        };
+        Ok::<_, Error>(texture.unwrap_or_else(|| fallback.clone()))
+    };
+
+    // Roughness and metallic share a texture, in its green and blue channels.
+    let metallic_roughness = load(
+        pbr.metallic_roughness_texture().map(|info| info.texture()),
+        TextureKind::Data,
+        &plain.roughness,
+    )?;
+    let material_textures = MaterialTextures {
+        base_color: load(
+            pbr.base_color_texture().map(|info| info.texture()),
+            TextureKind::Color,
+            &plain.base_color,
+        )?,
+        normal: load(
+            material.normal_texture().map(|info| info.texture()),
+            TextureKind::Normal,
+            &plain.normal,
+        )?,
+        roughness: metallic_roughness.clone(),
+        metallic: metallic_roughness,
+        occlusion: load(
+            material.occlusion_texture().map(|info| info.texture()),
+            TextureKind::Data,
+            &plain.occlusion,
+        )?,
+        emissive: load(
+            material.emissive_texture().map(|info| info.texture()),
+            TextureKind::Color,
+            &plain.emissive,
+        )?,
+    };
+
+    Ok(Material::new(
+        device,
+        name,
+        material_textures,
+        MaterialUniform::from_gltf(material),
+        layout,
+    ))
+}
+
+/// The image behind `texture` with the glTF sampler's wrapping and
+/// magnification filter, or `None` if its pixel format can't be uploaded.
+/// Images in their own files are shared with anything else loading them.
+fn load_texture(
+    texture: &gltf::Texture,
+    buffers: &[gltf::buffer::Data],
+    file_name: &str,
+    kind: TextureKind,
+    textures: &mut TextureCache,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> Result<Option<Arc<texture::Texture>>> {
+    let image = texture.source();
+    let path = match image.source() {
+        gltf::image::Source::Uri { uri, .. } if !uri.starts_with("data:") => {
+            assets::resolve(file_name, &percent_decode(uri))
+        }
+        _ => format!("{}#image{}", file_name, image.index()),
+    };
+
+    let sampler = texture.sampler();
+    let address_mode = |mode| match mode {
+        gltf::texture::WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+        gltf::texture::WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+        gltf::texture::WrappingMode::Repeat => wgpu::AddressMode::Repeat,
+    };
+    let defaults = textures.sampler();
+    let mag_filter = match sampler.mag_filter() {
+        Some(gltf::texture::MagFilter::Nearest) => wgpu::FilterMode::Nearest,
+        Some(gltf::texture::MagFilter::Linear) => wgpu::FilterMode::Linear,
+        None => defaults.mag_filter,
+    };
+    // Mips are always generated, so the plain filters keep the default
+    // filtering between levels.
+    let (min_filter, mipmap_filter) = {
+        use gltf::texture::MinFilter;
+        use wgpu::FilterMode::{Linear, Nearest};
+        match sampler.min_filter() {
+            Some(MinFilter::Nearest) => (Nearest, defaults.mipmap_filter),
+            Some(MinFilter::Linear) => (Linear, defaults.mipmap_filter),
+            Some(MinFilter::NearestMipmapNearest) => (Nearest, Nearest),
+            Some(MinFilter::LinearMipmapNearest) => (Linear, Nearest),
+            Some(MinFilter::NearestMipmapLinear) => (Nearest, Linear),
+            Some(MinFilter::LinearMipmapLinear) => (Linear, Linear),
+            None => (defaults.min_filter, defaults.mipmap_filter),
+        }
+    };
+    let sampler = SamplerOptions {
+        address_mode_u: address_mode(sampler.wrap_s()),
+        address_mode_v: address_mode(sampler.wrap_t()),
+        mag_filter,
+        min_filter,
+        mipmap_filter,
+        anisotropy: defaults.anisotropy,
+    };
+
+    let key = TextureKey {
+        path,
+        kind,
+        sampler,
+    };
+    if let Some(texture) = textures.get(&key) {
+        return Ok(Some(texture));
+    }
+
+    let Some(decoded) = decode_image(&image, buffers, file_name, textures.assets())? else {
+        return Ok(None);
+    };
+    let mut texture_out = texture::Texture::from_image(
+        device,
+        queue,
+        &decoded,
+        &key.path,
+        kind,
+        Some(textures.mipmaps()),
+    );
+    texture_out.sampler = sampler.create_sampler(device, &key.path);
+
+    Ok(Some(textures.insert(key, texture_out)))
+}
+
+/// Wraps decoded glTF pixel data, stored in native byte order, back up as an
+/// image.
+fn to_dynamic_image(data: &gltf::image::Data) -> Option<image::DynamicImage> {
+    use gltf::image::Format;
+    use image::{DynamicImage, ImageBuffer};
+
+    let (width, height) = (data.width, data.height);
+    let bytes = data.pixels.clone();
+    let u16s = || {
+        data.pixels
+            .chunks_exact(2)
+            .map(|c| u16::from_ne_bytes([c[0], c[1]]))
+            .collect::<Vec<_>>()
+    };
+    let f32s = || {
+        data.pixels
+            .chunks_exact(4)
+            .map(|c| f32::from_ne_bytes([c[0], c[1], c[2], c[3]]))
+            .collect::<Vec<_>>()
+    };
+
+    Some(match data.format {
+        Format::R8 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, bytes)?),
+        Format::R8G8 => DynamicImage::ImageLumaA8(ImageBuffer::from_raw(width, height, bytes)?),
+        Format::R8G8B8 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, bytes)?),
+        Format::R8G8B8A8 => DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, bytes)?),
+        Format::R16 => DynamicImage::ImageLuma16(ImageBuffer::from_raw(width, height, u16s())?),
+        Format::R16G16 => DynamicImage::ImageLumaA16(ImageBuffer::from_raw(width, height, u16s())?),
+        Format::R16G16B16 => {
+            DynamicImage::ImageRgb16(ImageBuffer::from_raw(width, height, u16s())?)
+        }
+        Format::R16G16B16A16 => {
+            DynamicImage::ImageRgba16(ImageBuffer::from_raw(width, height, u16s())?)
+        }
+        Format::R32G32B32FLOAT => {
+            DynamicImage::ImageRgb32F(ImageBuffer::from_raw(width, height, f32s())?)
+        }
+        Format::R32G32B32A32FLOAT => {
+            DynamicImage::ImageRgba32F(ImageBuffer::from_raw(width, height, f32s())?)
+        }
+    })
+}
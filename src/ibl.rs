@@ -0,0 +1,610 @@
+use std::{
+    f32::consts::PI,
+    path::{Path, PathBuf},
+};
+
+use cgmath::{InnerSpace, Vector3};
+use image::Rgba32FImage;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    environment, mipmap,
+    texture::{CubeTexture, Texture, TextureKind},
+};
+
+/// Width and height of each irradiance cube face. Irradiance changes slowly
+/// with direction, so it needs very few texels.
+pub const IRRADIANCE_SIZE: u32 = 32;
+/// Width and height of each face of the sharpest prefiltered specular level.
+pub const SPECULAR_SIZE: u32 = 128;
+/// Prefiltered specular levels, for roughness 0 in the first up to 1 in the
+/// last. Each is a mip level of the specular cube map.
+pub const SPECULAR_LEVELS: u32 = 5;
+/// Width and height of the BRDF lookup table.
+pub const BRDF_LUT_SIZE: u32 = 64;
+
+const SPECULAR_SAMPLES: u32 = 64;
+const BRDF_SAMPLES: u32 = 256;
+/// The environment is shrunk to faces this size before the irradiance
+/// convolution, which visits every one of its texels.
+const IRRADIANCE_SOURCE_SIZE: u32 = 16;
+/// Bumped whenever the maps would come out differently, so older cache
+/// files are recomputed instead of used.
+const CACHE_VERSION: u32 = 1;
+const CACHE_MAGIC: &[u8; 4] = b"IBL\0";
+
+/// Everything image based lighting needs from an environment, computed on
+/// the CPU.
+#[derive(Debug, Clone)]
+pub struct IblMaps {
+    /// Cosine weighted light from every direction, divided by pi, so the
+    /// diffuse term is albedo times irradiance.
+    pub irradiance: [Rgba32FImage; 6],
+    /// The environment blurred by a GGX lobe, one set of faces per roughness
+    /// level.
+    pub specular: Vec<[Rgba32FImage; 6]>,
+    /// Scale in red and bias in green applied to F0 by the split sum
+    /// approximation, for `n·v` across and roughness down.
+    pub brdf_lut: Rgba32FImage,
+}
+
+impl IblMaps {
+    pub fn compute(faces: &[Rgba32FImage; 6]) -> Self {
+        Self {
+            irradiance: irradiance_map(faces, IRRADIANCE_SIZE),
+            specular: prefilter_specular(faces, SPECULAR_SIZE, SPECULAR_LEVELS, SPECULAR_SAMPLES),
+            brdf_lut: brdf_lut(BRDF_LUT_SIZE, BRDF_SAMPLES),
+        }
+    }
+
+    /// Reads the maps for `faces` from `cache_dir`, or computes them and
+    /// saves them there for next time. Cache failures only cost the time
+    /// spent recomputing.
+    pub fn load_or_compute(faces: &[Rgba32FImage; 6], cache_dir: Option<&Path>) -> Self {
+        let path = cache_dir.map(|dir| dir.join(format!("ibl-{:016x}.bin", cache_key(faces))));
+
+        if let Some(maps) = path
+            .as_ref()
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|bytes| Self::from_bytes(&bytes))
+        {
+            log::info!("image based lighting read from {}", path.unwrap().display());
+            return maps;
+        }
+
+        let start = std::time::Instant::now();
+        let maps = Self::compute(faces);
+        log::info!("image based lighting computed in {:?}", start.elapsed());
+
+        if let Some(path) = path {
+            let written = path
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|()| std::fs::write(&path, maps.to_bytes()));
+            if let Err(e) = written {
+                log::warn!(
+                    "failed to cache image based lighting in {}: {}",
+                    path.display(),
+                    e
+                );
+            }
+        }
+
+        maps
+    }
+
+    /// The cache file contents: a header with the sizes, then every face of
+    /// every map as little endian `f32` RGBA.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let header = [
+            CACHE_VERSION,
+            self.irradiance[0].width(),
+            self.specular[0][0].width(),
+            self.specular.len() as u32,
+            self.brdf_lut.width(),
+        ];
+
+        let mut bytes = CACHE_MAGIC.to_vec();
+        bytes.extend(header.iter().flat_map(|value| value.to_le_bytes()));
+        let images = self
+            .irradiance
+            .iter()
+            .chain(self.specular.iter().flatten())
+            .chain([&self.brdf_lut]);
+        for image in images {
+            bytes.extend(image.as_raw().iter().flat_map(|value| value.to_le_bytes()));
+        }
+        bytes
+    }
+
+    /// `None` unless `bytes` came from [`Self::to_bytes`] of this version,
+    /// with maps of the sizes this build computes.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut words = bytes
+            .strip_prefix(CACHE_MAGIC)?
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()));
+        let header = [
+            CACHE_VERSION,
+            IRRADIANCE_SIZE,
+            SPECULAR_SIZE,
+            SPECULAR_LEVELS,
+            BRDF_LUT_SIZE,
+        ];
+        for expected in header {
+            if words.next()? != expected {
+                return None;
+            }
+        }
+
+        let mut read = |size: u32| {
+            let data = (&mut words)
+                .take((size * size * 4) as usize)
+                .map(f32::from_bits)
+                .collect();
+            Rgba32FImage::from_raw(size, size, data)
+        };
+        let mut read_faces = |size: u32| -> Option<[Rgba32FImage; 6]> {
+            let faces: Vec<_> = (0..6).map(|_| read(size)).collect::<Option<_>>()?;
+            faces.try_into().ok()
+        };
+
+        let irradiance = read_faces(IRRADIANCE_SIZE)?;
+        let specular = (0..SPECULAR_LEVELS)
+            .map(|level| read_faces((SPECULAR_SIZE >> level).max(1)))
+            .collect::<Option<_>>()?;
+        let brdf_lut = read(BRDF_LUT_SIZE)?;
+        if words.next().is_some() {
+            return None;
+        }
+
+        Some(Self {
+            irradiance,
+            specular,
+            brdf_lut,
+        })
+    }
+}
+
+/// Names the cache file for an environment: a hash of its faces and of the
+/// settings the maps are computed with.
+pub fn cache_key(faces: &[Rgba32FImage; 6]) -> u64 {
+    // FNV-1a, which unlike `DefaultHasher` gives the same hash every run.
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    let mut add = |word: u32| {
+        hash ^= word as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    };
+
+    for setting in [
+        CACHE_VERSION,
+        IRRADIANCE_SIZE,
+        SPECULAR_SIZE,
+        SPECULAR_LEVELS,
+        BRDF_LUT_SIZE,
+        SPECULAR_SAMPLES,
+        BRDF_SAMPLES,
+        IRRADIANCE_SOURCE_SIZE,
+    ] {
+        add(setting);
+    }
+    for face in faces {
+        add(face.width());
+        face.as_raw().iter().for_each(|value| add(value.to_bits()));
+    }
+    hash
+}
+
+/// Where IBL maps are cached by default: `hello_wgpu` under the user's cache
+/// directory, or under the system temporary directory without one.
+pub fn default_cache_dir() -> PathBuf {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir)
+        .join("hello_wgpu")
+}
+
+/// Convolves the environment with a cosine lobe around each direction.
+pub fn irradiance_map(faces: &[Rgba32FImage; 6], size: u32) -> [Rgba32FImage; 6] {
+    let source = faces.each_ref().map(|face| {
+        let mut face = face.clone();
+        while face.width() > IRRADIANCE_SOURCE_SIZE {
+            face = mipmap::downsample_hdr(&face);
+        }
+        face
+    });
+
+    // Every source texel as a direction and its radiance times the solid
+    // angle it covers.
+    let source_size = source[0].width();
+    let texels: Vec<(Vector3<f32>, [f32; 3])> = (0..6)
+        .flat_map(|face| {
+            let image = &source[face];
+            image.enumerate_pixels().map(move |(x, y, pixel)| {
+                let u = (x as f32 + 0.5) / source_size as f32 * 2.0 - 1.0;
+                let v = (y as f32 + 0.5) / source_size as f32 * 2.0 - 1.0;
+                let solid_angle =
+                    (2.0 / source_size as f32).powi(2) / (1.0 + u * u + v * v).powf(1.5);
+                let [r, g, b, _] = pixel.0;
+                (
+                    environment::cube_face_direction(face, u, v),
+                    [r * solid_angle, g * solid_angle, b * solid_angle],
+                )
+            })
+        })
+        .collect();
+
+    per_face(size, |normal| {
+        let mut sum = [0.0; 3];
+        for (direction, radiance) in &texels {
+            let cosine = normal.dot(*direction);
+            if cosine > 0.0 {
+                for (sum, radiance) in sum.iter_mut().zip(radiance) {
+                    *sum += radiance * cosine;
+                }
+            }
+        }
+        [sum[0] / PI, sum[1] / PI, sum[2] / PI, 1.0]
+    })
+}
+
+/// Blurs the environment by GGX lobes of increasing roughness, one level per
+/// roughness from 0 to 1, each half the size of the one before it. Uses
+/// filtered importance sampling: samples the lobe is unlikely to pick read
+/// blurrier mips of the environment, so few samples give smooth results.
+pub fn prefilter_specular(
+    faces: &[Rgba32FImage; 6],
+    size: u32,
+    levels: u32,
+    samples: u32,
+) -> Vec<[Rgba32FImage; 6]> {
+    let source = mipmap::generate_cube_mips(faces);
+
+    let source_size = faces[0].width() as f32;
+    let sample_lod = |direction: Vector3<f32>, lod: f32| {
+        let lod = lod.clamp(0.0, (source.len() - 1) as f32);
+        let (low, high) = (lod.floor() as usize, lod.ceil() as usize);
+        let a = environment::sample_cube(&source[low], direction);
+        let b = environment::sample_cube(&source[high], direction);
+        let t = lod - low as f32;
+        std::array::from_fn(|channel| a[channel] + (b[channel] - a[channel]) * t)
+    };
+    let texel_solid_angle = 4.0 * PI / (6.0 * source_size * source_size);
+
+    (0..levels)
+        .map(|level| {
+            let level_size = (size >> level).max(1);
+            let roughness = level as f32 / (levels - 1).max(1) as f32;
+
+            if roughness == 0.0 {
+                let lod = (source_size / level_size as f32).log2();
+                return per_face(level_size, |direction| sample_lod(direction, lod));
+            }
+
+            per_face(level_size, |normal| {
+                // Reflections are assumed to be seen head on, with the view
+                // direction along the normal.
+                let mut sum = [0.0; 3];
+                let mut weight = 0.0;
+                for i in 0..samples {
+                    let half = importance_sample_ggx(hammersley(i, samples), normal, roughness);
+                    let n_dot_h = normal.dot(half).max(0.0);
+                    let light = half * 2.0 * n_dot_h - normal;
+                    let n_dot_l = normal.dot(light);
+                    if n_dot_l <= 0.0 {
+                        continue;
+                    }
+
+                    let pdf = ggx_distribution(n_dot_h, roughness) / 4.0 + 1e-4;
+                    let sample_solid_angle = 1.0 / (samples as f32 * pdf);
+                    let lod = 0.5 * (sample_solid_angle / texel_solid_angle).log2() + 1.0;
+
+                    let radiance = sample_lod(light, lod);
+                    for (sum, radiance) in sum.iter_mut().zip(radiance) {
+                        *sum += radiance * n_dot_l;
+                    }
+                    weight += n_dot_l;
+                }
+                let weight = weight.max(1e-4);
+                [sum[0] / weight, sum[1] / weight, sum[2] / weight, 1.0]
+            })
+        })
+        .collect()
+}
+
+/// Integrates the GGX specular BRDF with Schlick's Fresnel split out, as
+/// in Karis' "Real Shading in Unreal Engine 4".
+pub fn brdf_lut(size: u32, samples: u32) -> Rgba32FImage {
+    Rgba32FImage::from_fn(size, size, |x, y| {
+        let n_dot_v = (x as f32 + 0.5) / size as f32;
+        let roughness = (y as f32 + 0.5) / size as f32;
+        let view = Vector3::new((1.0 - n_dot_v * n_dot_v).sqrt(), 0.0, n_dot_v);
+        let normal = Vector3::unit_z();
+
+        let (mut scale, mut bias) = (0.0, 0.0);
+        for i in 0..samples {
+            let half = importance_sample_ggx(hammersley(i, samples), normal, roughness);
+            let v_dot_h = view.dot(half).max(0.0);
+            let light = half * 2.0 * v_dot_h - view;
+            let (n_dot_l, n_dot_h) = (light.z, half.z.max(0.0));
+            if n_dot_l <= 0.0 {
+                continue;
+            }
+
+            let visibility =
+                smith_geometry(n_dot_v, n_dot_l, roughness) * v_dot_h / (n_dot_h * n_dot_v);
+            let fresnel = (1.0 - v_dot_h).powi(5);
+            scale += (1.0 - fresnel) * visibility;
+            bias += fresnel * visibility;
+        }
+        image::Rgba([scale / samples as f32, bias / samples as f32, 0.0, 1.0])
+    })
+}
+
+/// Builds six `size` square faces in parallel, from the color in the
+/// direction through each texel's center.
+fn per_face(size: u32, color: impl Fn(Vector3<f32>) -> [f32; 4] + Sync) -> [Rgba32FImage; 6] {
+    let color = &color;
+    std::thread::scope(|scope| {
+        let faces = [0, 1, 2, 3, 4, 5].map(|face| {
+            scope.spawn(move || {
+                Rgba32FImage::from_fn(size, size, |x, y| {
+                    let u = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                    let v = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                    image::Rgba(color(environment::cube_face_direction(face, u, v)))
+                })
+            })
+        });
+        faces.map(|face| face.join().expect("IBL face thread panicked"))
+    })
+}
+
+/// Point `i` of an `n` point low discrepancy sequence over the unit square.
+fn hammersley(i: u32, n: u32) -> (f32, f32) {
+    (i as f32 / n as f32, i.reverse_bits() as f32 / 2f32.powi(32))
+}
+
+/// A half vector around `normal`, distributed like GGX with `roughness`.
+fn importance_sample_ggx((u, v): (f32, f32), normal: Vector3<f32>, roughness: f32) -> Vector3<f32> {
+    let alpha = roughness * roughness;
+    let phi = 2.0 * PI * u;
+    let cos_theta = ((1.0 - v) / (1.0 + (alpha * alpha - 1.0) * v)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+    let up = if normal.z.abs() < 0.999 {
+        Vector3::unit_z()
+    } else {
+        Vector3::unit_x()
+    };
+    let tangent = up.cross(normal).normalize();
+    let bitangent = normal.cross(tangent);
+
+    (tangent * (sin_theta * phi.cos()) + bitangent * (sin_theta * phi.sin()) + normal * cos_theta)
+        .normalize()
+}
+
+fn ggx_distribution(n_dot_h: f32, roughness: f32) -> f32 {
+    let alpha2 = roughness.powi(4);
+    let denominator = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    alpha2 / (PI * denominator * denominator)
+}
+
+/// Smith's shadowing and masking with Schlick's approximation, using the
+/// `k` that suits image based lighting.
+fn smith_geometry(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = roughness * roughness / 2.0;
+    let schlick = |n_dot_x: f32| n_dot_x / (n_dot_x * (1.0 - k) + k);
+    schlick(n_dot_v) * schlick(n_dot_l)
+}
+
+/// Tells the shader how to use the maps. Matches `Environment` in the shader.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct EnvironmentUniform {
+    /// Scale of the environment's light. Zero without an environment, which
+    /// makes the shader fall back to a flat ambient term.
+    pub intensity: f32,
+    /// Mip levels of the specular cube map.
+    specular_levels: f32,
+    _padding: [f32; 2],
+}
+
+/// The IBL maps uploaded for the main pass, bound with the lights.
+pub struct Ibl {
+    pub irradiance: CubeTexture,
+    pub specular: CubeTexture,
+    pub brdf_lut: Texture,
+    pub uniform: EnvironmentUniform,
+    pub buffer: wgpu::Buffer,
+}
+
+impl Ibl {
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, maps: &IblMaps) -> Self {
+        Self::with_intensity(device, queue, maps, 1.0)
+    }
+
+    /// Black maps and no intensity, for scenes without an environment.
+    pub fn none(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let black = || Rgba32FImage::from_pixel(1, 1, image::Rgba([0.0, 0.0, 0.0, 1.0]));
+        let maps = IblMaps {
+            irradiance: std::array::from_fn(|_| black()),
+            specular: vec![std::array::from_fn(|_| black())],
+            brdf_lut: black(),
+        };
+        Self::with_intensity(device, queue, &maps, 0.0)
+    }
+
+    fn with_intensity(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        maps: &IblMaps,
+        intensity: f32,
+    ) -> Self {
+        let irradiance = CubeTexture::from_levels(
+            device,
+            queue,
+            std::slice::from_ref(&maps.irradiance),
+            Self::FORMAT,
+            "irradiance map",
+        );
+        let specular = CubeTexture::from_levels(
+            device,
+            queue,
+            &maps.specular,
+            Self::FORMAT,
+            "prefiltered specular map",
+        );
+        let brdf_lut = Texture::from_image(
+            device,
+            queue,
+            &image::DynamicImage::ImageRgba32F(maps.brdf_lut.clone()),
+            "BRDF lookup table",
+            TextureKind::Hdr,
+            None,
+        );
+
+        let uniform = EnvironmentUniform {
+            intensity,
+            specular_levels: maps.specular.len() as f32,
+            _padding: [0.0; 2],
+        };
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("environment buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self {
+            irradiance,
+            specular,
+            brdf_lut,
+            uniform,
+            buffer,
+        }
+    }
+
+    /// Layout of [`Self::bind_group_entries`]: the irradiance and specular
+    /// cube maps, the BRDF lookup table, one sampler for all three and the
+    /// uniform, from binding `first` on.
+    pub fn bind_group_layout_entries(first: u32) -> [wgpu::BindGroupLayoutEntry; 5] {
+        let texture = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        };
+
+        [
+            texture(first, wgpu::TextureViewDimension::Cube),
+            texture(first + 1, wgpu::TextureViewDimension::Cube),
+            texture(first + 2, wgpu::TextureViewDimension::D2),
+            wgpu::BindGroupLayoutEntry {
+                binding: first + 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: first + 4,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ]
+    }
+
+    pub fn bind_group_entries(&self, first: u32) -> [wgpu::BindGroupEntry<'_>; 5] {
+        [
+            wgpu::BindGroupEntry {
+                binding: first,
+                resource: wgpu::BindingResource::TextureView(&self.irradiance.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: first + 1,
+                resource: wgpu::BindingResource::TextureView(&self.specular.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: first + 2,
+                resource: wgpu::BindingResource::TextureView(&self.brdf_lut.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: first + 3,
+                resource: wgpu::BindingResource::Sampler(&self.irradiance.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: first + 4,
+                resource: self.buffer.as_entire_binding(),
+            },
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Maps of the cached sizes, each texel holding a different value.
+    fn maps() -> IblMaps {
+        let mut next = 0.0;
+        let mut image = |size: u32| {
+            Rgba32FImage::from_fn(size, size, |_, _| {
+                next += 1.0;
+                image::Rgba([next, 0.5, -next, 1.0])
+            })
+        };
+        let mut faces = |size: u32| std::array::from_fn(|_| image(size));
+
+        IblMaps {
+            irradiance: faces(IRRADIANCE_SIZE),
+            specular: (0..SPECULAR_LEVELS)
+                .map(|level| faces((SPECULAR_SIZE >> level).max(1)))
+                .collect(),
+            brdf_lut: image(BRDF_LUT_SIZE),
+        }
+    }
+
+    /// Replaces header word `index`, after the magic.
+    fn with_header(mut bytes: Vec<u8>, index: usize, value: u32) -> Vec<u8> {
+        let offset = CACHE_MAGIC.len() + index * 4;
+        bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn cache_bytes_round_trip() {
+        let maps = maps();
+        let read = IblMaps::from_bytes(&maps.to_bytes()).unwrap();
+        assert_eq!(read.irradiance, maps.irradiance);
+        assert_eq!(read.specular, maps.specular);
+        assert_eq!(read.brdf_lut, maps.brdf_lut);
+    }
+
+    #[test]
+    fn corrupted_headers_are_rejected() {
+        let bytes = maps().to_bytes();
+        // Version, irradiance size, specular size, levels and LUT size.
+        for (index, value) in [(0, 0), (1, 0), (2, 1 << 20), (3, 40), (4, u32::MAX)] {
+            let corrupted = with_header(bytes.clone(), index, value);
+            assert!(
+                IblMaps::from_bytes(&corrupted).is_none(),
+                "header {}",
+                index
+            );
+        }
+
+        assert!(IblMaps::from_bytes(&bytes[..bytes.len() - 4]).is_none());
+        assert!(IblMaps::from_bytes(&bytes[..CACHE_MAGIC.len() + 8]).is_none());
+        assert!(IblMaps::from_bytes(b"nope").is_none());
+    }
+}
@@ -0,0 +1,24 @@
+pub mod adapter;
+pub mod assets;
+pub mod camera;
+pub mod camera_controller;
+pub mod clock;
+pub mod decompress;
+pub mod environment;
+pub mod error;
+pub mod gltf_loader;
+pub mod ibl;
+pub mod instance;
+pub mod light;
+pub mod mipmap;
+pub mod model;
+pub mod settings;
+pub mod shadow;
+pub mod skybox;
+pub mod swapchain;
+pub mod texture;
+pub mod texture_cache;
+pub mod texture_container;
+pub mod triangle_model;
+pub mod vertex_buffer;
+pub mod window;
@@ -0,0 +1,709 @@
+use cgmath::{InnerSpace, Rotation3};
+use wgpu::util::DeviceExt;
+
+use crate::{ibl::Ibl, shadow};
+
+/// How the light illuminates the scene. Point lights cast shadows into cube
+/// maps, the others into a single projected shadow map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightKind {
+    Point,
+    Directional,
+    Spot,
+}
+
+impl LightKind {
+    /// Value of `Light::kind` in the shaders.
+    fn shader_value(self) -> u32 {
+        match self {
+            LightKind::Point => 0,
+            LightKind::Directional => 1,
+            LightKind::Spot => 2,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightUniform {
+    pub position: [f32; 3],
+    /// Fraction of the light color applied everywhere, regardless of normals.
+    pub ambient_strength: f32,
+    pub color: [f32; 3],
+    kind: u32,
+    /// Normalized direction the light shines in. Unused by point lights.
+    pub direction: [f32; 3],
+    /// Cosine of the angle inside which a spot light is at full strength.
+    pub inner_cutoff: f32,
+    /// Cosine of the angle outside which a spot light is dark.
+    pub outer_cutoff: f32,
+    _padding: [f32; 3],
+}
+
+impl LightUniform {
+    pub fn new(kind: LightKind, position: [f32; 3], color: [f32; 3]) -> Self {
+        let mut uniform = Self {
+            position,
+            ambient_strength: 0.1,
+            color,
+            kind: kind.shader_value(),
+            direction: [0.0, -1.0, 0.0],
+            inner_cutoff: LightInit::SPOT_INNER_ANGLE.to_radians().cos(),
+            outer_cutoff: LightInit::SPOT_OUTER_ANGLE.to_radians().cos(),
+            _padding: [0.0; 3],
+        };
+        uniform.aim_at(cgmath::Point3::new(0.0, 0.0, 0.0));
+        uniform
+    }
+
+    /// Points the light at `target`, or straight down when it is at
+    /// `target` already.
+    pub fn aim_at(&mut self, target: cgmath::Point3<f32>) {
+        let position = cgmath::Point3::from(self.position);
+        self.direction = unit_direction((target - position).into());
+    }
+}
+
+/// `direction` scaled to unit length, or straight down when it has no
+/// length to scale, so the shaders never see NaNs.
+fn unit_direction(direction: [f32; 3]) -> [f32; 3] {
+    let direction = cgmath::Vector3::from(direction);
+    let length = direction.magnitude();
+    if length > 0.0 && length.is_finite() {
+        (direction / length).into()
+    } else {
+        [0.0, -1.0, 0.0]
+    }
+}
+
+/// Most lights the scene can have besides the main light. Adapters whose
+/// buffer limits can't hold this many get as many as fit, see
+/// [`LightList::capacity`].
+pub const MAX_LIGHTS: usize = 256;
+
+/// Most point lights in the list that cast shadows. Matches the array size
+/// in `PointShadows` in the shader, less the main light.
+pub const MAX_SHADOWED_LIGHTS: usize = 4;
+
+/// A light shining alongside the main light.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    /// Unused by directional lights.
+    pub position: [f32; 3],
+    /// Direction the light shines in. Unused by point lights.
+    pub direction: [f32; 3],
+    pub color: [f32; 3],
+    /// Distance at which point and spot lights have faded out completely.
+    pub range: f32,
+    /// Half angle of a spot light's cone inside which it is at full
+    /// strength, in degrees.
+    pub inner_angle: f32,
+    /// Half angle outside which a spot light is dark, in degrees.
+    pub outer_angle: f32,
+    /// Whether a point light renders a shadow cube map. Only the lights
+    /// present when the scene is created get one, see
+    /// [`LightList::shadow_cubes`].
+    pub casts_shadow: bool,
+}
+
+impl Light {
+    /// Panics if `range` isn't positive.
+    pub fn point(position: [f32; 3], color: [f32; 3], range: f32) -> Self {
+        assert!(range > 0.0, "light range must be positive, got {}", range);
+        Self {
+            kind: LightKind::Point,
+            position,
+            direction: [0.0, -1.0, 0.0],
+            color,
+            range,
+            inner_angle: 0.0,
+            outer_angle: 0.0,
+            casts_shadow: false,
+        }
+    }
+
+    /// A zero `direction` shines straight down.
+    pub fn directional(direction: [f32; 3], color: [f32; 3]) -> Self {
+        Self {
+            kind: LightKind::Directional,
+            position: [0.0; 3],
+            direction: unit_direction(direction),
+            color,
+            range: 0.0,
+            inner_angle: 0.0,
+            outer_angle: 0.0,
+            casts_shadow: false,
+        }
+    }
+
+    /// A zero `direction` shines straight down. Panics if `range` isn't
+    /// positive.
+    pub fn spot(
+        position: [f32; 3],
+        direction: [f32; 3],
+        color: [f32; 3],
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Self {
+        Self {
+            kind: LightKind::Spot,
+            direction: unit_direction(direction),
+            inner_angle,
+            outer_angle,
+            ..Self::point(position, color, range)
+        }
+    }
+
+    fn uniform(&self) -> ListLightUniform {
+        ListLightUniform {
+            position: self.position,
+            range: self.range,
+            color: self.color,
+            kind: self.kind.shader_value(),
+            direction: unit_direction(self.direction),
+            inner_cutoff: self.inner_angle.to_radians().cos(),
+            outer_cutoff: self.outer_angle.to_radians().cos(),
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+/// A [`Light`] as the shaders see it, laid out to match `ListLight`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ListLightUniform {
+    position: [f32; 3],
+    range: f32,
+    color: [f32; 3],
+    kind: u32,
+    direction: [f32; 3],
+    inner_cutoff: f32,
+    outer_cutoff: f32,
+    _padding: [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightCountUniform {
+    count: u32,
+    /// The first `shadowed` lights have a shadow cube each, in order.
+    shadowed: u32,
+    _padding: [u32; 2],
+}
+
+/// Refers to a light in a [`LightList`], staying valid as other lights are
+/// added and removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LightId(u64);
+
+/// How the light list is bound to the shaders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightBinding {
+    /// A runtime sized storage buffer.
+    Storage,
+    /// A fixed size uniform array, for adapters without storage buffers in
+    /// vertex and fragment shaders.
+    Uniform,
+}
+
+/// The lights shining alongside the main light, uploaded every frame.
+pub struct LightList {
+    lights: Vec<(LightId, Light)>,
+    next_id: u64,
+    binding: LightBinding,
+    capacity: usize,
+    shadow_cubes: usize,
+    pub buffer: wgpu::Buffer,
+    pub count_buffer: wgpu::Buffer,
+}
+
+impl LightList {
+    /// How the shaders declare the list. [`LightList::shader_source`]
+    /// replaces it when the list is a uniform array.
+    const STORAGE_DECLARATION: &'static str = "var<storage, read> lights: array<ListLight>;";
+
+    /// An empty list, bound as a storage buffer where the adapter allows
+    /// and holding as many lights as the device's buffer limits allow, up
+    /// to [`MAX_LIGHTS`]. Up to `shadow_cubes` point lights cast shadows.
+    pub fn new(device: &wgpu::Device, adapter: &wgpu::Adapter, shadow_cubes: usize) -> Self {
+        let limits = device.limits();
+        let flags = adapter.get_downlevel_capabilities().flags;
+        let storage = limits.max_storage_buffers_per_shader_stage > 0
+            && flags.contains(
+                wgpu::DownlevelFlags::VERTEX_STORAGE | wgpu::DownlevelFlags::FRAGMENT_STORAGE,
+            );
+        let (binding, max_size) = if storage {
+            (
+                LightBinding::Storage,
+                limits.max_storage_buffer_binding_size,
+            )
+        } else {
+            (
+                LightBinding::Uniform,
+                limits.max_uniform_buffer_binding_size,
+            )
+        };
+
+        let stride = std::mem::size_of::<ListLightUniform>();
+        let capacity = MAX_LIGHTS.min(max_size as usize / stride).max(1);
+        if binding == LightBinding::Uniform || capacity < MAX_LIGHTS {
+            log::info!("up to {capacity} lights in a {binding:?} buffer");
+        }
+
+        let usage = match binding {
+            LightBinding::Storage => wgpu::BufferUsages::STORAGE,
+            LightBinding::Uniform => wgpu::BufferUsages::UNIFORM,
+        };
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("light list buffer"),
+            size: (capacity * stride) as wgpu::BufferAddress,
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let count_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("light count buffer"),
+            contents: bytemuck::cast_slice(&[LightCountUniform {
+                count: 0,
+                shadowed: 0,
+                _padding: [0; 2],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self {
+            lights: Vec::new(),
+            next_id: 0,
+            binding,
+            capacity,
+            shadow_cubes: shadow_cubes.min(MAX_SHADOWED_LIGHTS),
+            buffer,
+            count_buffer,
+        }
+    }
+
+    /// Adds `light` after the others. Lights past the list's capacity are
+    /// kept but not drawn until earlier ones are removed. Panics if a point
+    /// or spot light's range isn't positive.
+    pub fn add(&mut self, light: Light) -> LightId {
+        assert!(
+            light.kind == LightKind::Directional || light.range > 0.0,
+            "light range must be positive, got {}",
+            light.range
+        );
+        let id = LightId(self.next_id);
+        self.next_id += 1;
+        self.lights.push((id, light));
+        if self.lights.len() == self.capacity + 1 {
+            log::warn!("more than {} lights, the rest are not drawn", self.capacity);
+        }
+        id
+    }
+
+    pub fn get(&self, id: LightId) -> Option<&Light> {
+        self.lights
+            .iter()
+            .find(|(i, _)| *i == id)
+            .map(|(_, light)| light)
+    }
+
+    pub fn get_mut(&mut self, id: LightId) -> Option<&mut Light> {
+        self.lights
+            .iter_mut()
+            .find(|(i, _)| *i == id)
+            .map(|(_, light)| light)
+    }
+
+    pub fn remove(&mut self, id: LightId) -> Option<Light> {
+        let index = self.lights.iter().position(|(i, _)| *i == id)?;
+        Some(self.lights.remove(index).1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (LightId, &Light)> {
+        self.lights.iter().map(|(id, light)| (*id, light))
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    pub fn binding(&self) -> LightBinding {
+        self.binding
+    }
+
+    /// Most lights the shaders see.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Most point lights with a shadow cube map.
+    pub fn shadow_cubes(&self) -> usize {
+        self.shadow_cubes
+    }
+
+    /// The lights the shaders see, in the order they see them, and how many
+    /// of them cast shadows. Shadow casting point lights come first, up to
+    /// [`LightList::shadow_cubes`] of them; the rest keep their order.
+    pub fn drawn(&self) -> (Vec<&Light>, usize) {
+        let (mut drawn, rest): (Vec<_>, Vec<_>) = self
+            .lights
+            .iter()
+            .take(self.capacity)
+            .map(|(_, light)| light)
+            .partition(|light| light.casts_shadow && light.kind == LightKind::Point);
+        let shadowed = drawn.len().min(self.shadow_cubes);
+        let unshadowed = drawn.split_off(shadowed);
+        drawn.extend(unshadowed);
+        drawn.extend(rest);
+        (drawn, shadowed)
+    }
+
+    /// `source` with the list declared the way it is bound. `source` must
+    /// declare it as `var<storage, read> lights: array<ListLight>;`.
+    pub fn shader_source(&self, source: &str) -> String {
+        debug_assert!(
+            source.contains(Self::STORAGE_DECLARATION),
+            "shader does not declare the light list as `{}`",
+            Self::STORAGE_DECLARATION
+        );
+        match self.binding {
+            LightBinding::Storage => source.to_string(),
+            LightBinding::Uniform => source.replace(
+                Self::STORAGE_DECLARATION,
+                &format!("var<uniform> lights: array<ListLight, {}>;", self.capacity),
+            ),
+        }
+    }
+
+    fn bind_group_layout_entries(&self, first: u32) -> [wgpu::BindGroupLayoutEntry; 2] {
+        let ty = match self.binding {
+            LightBinding::Storage => wgpu::BufferBindingType::Storage { read_only: true },
+            LightBinding::Uniform => wgpu::BufferBindingType::Uniform,
+        };
+        [
+            wgpu::BindGroupLayoutEntry {
+                binding: first,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: first + 1,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ]
+    }
+
+    fn bind_group_entries(&self, first: u32) -> [wgpu::BindGroupEntry<'_>; 2] {
+        [
+            wgpu::BindGroupEntry {
+                binding: first,
+                resource: self.count_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: first + 1,
+                resource: self.buffer.as_entire_binding(),
+            },
+        ]
+    }
+
+    pub fn write(&self, queue: &wgpu::Queue) {
+        let (drawn, shadowed) = self.drawn();
+        let uniforms: Vec<_> = drawn.iter().map(|light| light.uniform()).collect();
+        if !uniforms.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&uniforms));
+        }
+        queue.write_buffer(
+            &self.count_buffer,
+            0,
+            bytemuck::cast_slice(&[LightCountUniform {
+                count: uniforms.len() as u32,
+                shadowed: shadowed as u32,
+                _padding: [0; 2],
+            }]),
+        );
+    }
+}
+
+pub struct LightInit {
+    pub kind: LightKind,
+    pub light_uniform: LightUniform,
+    pub light_buffer: wgpu::Buffer,
+    /// Lights besides the main one, bound after it.
+    pub lights: LightList,
+    /// Point lights created with the scene that orbit it.
+    orbiting: Vec<LightId>,
+    /// Light from the surroundings, bound after the light list.
+    pub environment: Ibl,
+    pub light_bind_group_layout: wgpu::BindGroupLayout,
+    pub light_bind_group: wgpu::BindGroup,
+}
+
+impl LightInit {
+    /// How fast the light orbits the scene's y axis, in degrees per second.
+    const ORBIT_SPEED: f32 = 60.0;
+    /// Half angles of a spot light's cone, in degrees.
+    const SPOT_INNER_ANGLE: f32 = 20.0;
+    const SPOT_OUTER_ANGLE: f32 = 30.0;
+
+    /// The main light of `kind` and up to [`MAX_SHADOWED_LIGHTS`] shadow
+    /// casting point lights, colored and spread evenly around a circle above
+    /// the scene.
+    pub fn new(
+        device: &wgpu::Device,
+        adapter: &wgpu::Adapter,
+        kind: LightKind,
+        point_light_count: usize,
+        environment: Ibl,
+    ) -> Self {
+        // Shadow casting lights sit higher and further out, so their shadows
+        // fall across the grid of instances.
+        let position = match kind {
+            LightKind::Point => [2.0, 2.0, 2.0],
+            LightKind::Directional | LightKind::Spot => [8.0, 6.0, 8.0],
+        };
+        let light_uniform = LightUniform::new(kind, position, [1.0, 1.0, 1.0]);
+
+        let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("light buffer"),
+            contents: bytemuck::cast_slice(&[light_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        const COLORS: [[f32; 3]; MAX_SHADOWED_LIGHTS] = [
+            [1.0, 0.3, 0.3],
+            [0.3, 1.0, 0.3],
+            [0.3, 0.3, 1.0],
+            [1.0, 1.0, 0.3],
+        ];
+
+        let count = point_light_count.min(MAX_SHADOWED_LIGHTS);
+        let shadow_cubes =
+            shadow::max_point_shadows(adapter) - usize::from(kind == LightKind::Point);
+        if count > shadow_cubes {
+            log::warn!(
+                "adapter has no cube array textures, only {} of {} point lights cast shadows",
+                shadow_cubes,
+                count
+            );
+        }
+        let mut lights = LightList::new(device, adapter, count.min(shadow_cubes));
+        let orbiting = (0..count)
+            .map(|i| {
+                let angle = std::f32::consts::TAU * i as f32 / count as f32;
+                let position = [6.0 * angle.cos(), 3.0, 6.0 * angle.sin()];
+                lights.add(Light {
+                    casts_shadow: true,
+                    ..Light::point(position, COLORS[i], 20.0)
+                })
+            })
+            .collect();
+
+        let mut layout_entries = vec![wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }];
+        layout_entries.extend(lights.bind_group_layout_entries(1));
+        layout_entries.extend(Ibl::bind_group_layout_entries(3));
+        let light_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("light bind group layout"),
+                entries: &layout_entries,
+            });
+
+        let mut entries = vec![wgpu::BindGroupEntry {
+            binding: 0,
+            resource: light_buffer.as_entire_binding(),
+        }];
+        entries.extend(lights.bind_group_entries(1));
+        entries.extend(environment.bind_group_entries(3));
+        let light_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("light bind group"),
+            layout: &light_bind_group_layout,
+            entries: &entries,
+        });
+
+        Self {
+            kind,
+            light_uniform,
+            light_buffer,
+            lights,
+            orbiting,
+            environment,
+            light_bind_group_layout,
+            light_bind_group,
+        }
+    }
+
+    /// Orbits the lights around the y axis by `dt` seconds worth of movement,
+    /// keeping the main light aimed at the origin. The point lights created
+    /// with the scene orbit the other way.
+    pub fn update(&mut self, dt: f32) {
+        let rotation = cgmath::Quaternion::from_axis_angle(
+            cgmath::Vector3::unit_y(),
+            cgmath::Deg(Self::ORBIT_SPEED * dt),
+        );
+        let position: cgmath::Vector3<f32> = self.light_uniform.position.into();
+        self.light_uniform.position = (rotation * position).into();
+        self.light_uniform
+            .aim_at(cgmath::Point3::new(0.0, 0.0, 0.0));
+
+        let rotation = rotation.conjugate();
+        for &id in &self.orbiting {
+            if let Some(light) = self.lights.get_mut(id) {
+                light.position = (rotation * cgmath::Vector3::from(light.position)).into();
+            }
+        }
+    }
+
+    pub fn write(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(
+            &self.light_buffer,
+            0,
+            bytemuck::cast_slice(&[self.light_uniform]),
+        );
+        self.lights.write(queue);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapter::AdapterConfig;
+
+    /// An empty list on the default adapter, or `None` to skip the test
+    /// when there is no adapter.
+    fn list(shadow_cubes: usize) -> Option<LightList> {
+        let config = AdapterConfig::default();
+        let instance = config.create_instance();
+        let adapter = pollster::block_on(config.select_adapter(&instance, None)).ok()?;
+        let descriptor = wgpu::DeviceDescriptor {
+            limits: adapter.limits(),
+            ..Default::default()
+        };
+        let (device, _) = pollster::block_on(adapter.request_device(&descriptor, None)).ok()?;
+        Some(LightList::new(&device, &adapter, shadow_cubes))
+    }
+
+    fn red(range: f32) -> Light {
+        Light::point([0.0; 3], [1.0, 0.0, 0.0], range)
+    }
+
+    #[test]
+    fn ids_stay_valid_as_lights_are_removed() {
+        let Some(mut list) = list(0) else {
+            eprintln!("skipping light list test: no adapter");
+            return;
+        };
+        let ids: Vec<_> = (1..=4).map(|range| list.add(red(range as f32))).collect();
+
+        assert_eq!(list.remove(ids[1]).map(|light| light.range), Some(2.0));
+        assert_eq!(list.remove(ids[1]), None);
+        assert_eq!(list.get(ids[1]), None);
+        assert_eq!(list.get(ids[2]).map(|light| light.range), Some(3.0));
+
+        // New lights get a new id rather than reusing the removed one.
+        let added = list.add(red(5.0));
+        assert!(!ids.contains(&added));
+        let ranges: Vec<_> = list.iter().map(|(_, light)| light.range).collect();
+        assert_eq!(ranges, [1.0, 3.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn lights_past_the_capacity_are_not_drawn() {
+        let Some(mut list) = list(0) else {
+            eprintln!("skipping light list test: no adapter");
+            return;
+        };
+        let capacity = list.capacity();
+        let first = list.add(red(1.0));
+        for _ in 0..capacity {
+            list.add(red(2.0));
+        }
+        assert_eq!(list.len(), capacity + 1);
+        let (drawn, _) = list.drawn();
+        assert_eq!(drawn.len(), capacity);
+
+        // Removing a drawn light brings the last one into view.
+        list.remove(first);
+        let (drawn, _) = list.drawn();
+        assert_eq!(drawn.len(), capacity);
+        assert!(drawn.iter().all(|light| light.range == 2.0));
+    }
+
+    #[test]
+    fn shadowed_lights_are_drawn_first() {
+        let Some(mut list) = list(2) else {
+            eprintln!("skipping light list test: no adapter");
+            return;
+        };
+        let shadowed = |range| Light {
+            casts_shadow: true,
+            ..red(range)
+        };
+        list.add(red(1.0));
+        list.add(shadowed(2.0));
+        list.add(Light::spot([0.0; 3], [0.0; 3], [1.0; 3], 3.0, 10.0, 20.0));
+        list.add(shadowed(4.0));
+        list.add(shadowed(5.0));
+
+        // Only two of the three shadow casting point lights get a cube, the
+        // third keeps its place ahead of the others.
+        let (drawn, count) = list.drawn();
+        let ranges: Vec<_> = drawn.iter().map(|light| light.range).collect();
+        assert_eq!(ranges, [2.0, 4.0, 5.0, 1.0, 3.0]);
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn zero_directions_shine_down() {
+        let down = [0.0, -1.0, 0.0];
+        assert_eq!(Light::directional([0.0; 3], [1.0; 3]).direction, down);
+        assert_eq!(
+            Light::directional([0.0, 0.0, 2.0], [1.0; 3]).direction,
+            [0.0, 0.0, 1.0]
+        );
+
+        let mut light = Light::spot([0.0; 3], [1.0, 0.0, 0.0], [1.0; 3], 1.0, 10.0, 20.0);
+        light.direction = [0.0; 3];
+        assert_eq!(light.uniform().direction, down);
+
+        let mut uniform = LightUniform::new(LightKind::Spot, [1.0; 3], [1.0; 3]);
+        uniform.aim_at(cgmath::Point3::new(1.0, 1.0, 1.0));
+        assert_eq!(uniform.direction, down);
+    }
+
+    #[test]
+    #[should_panic(expected = "light range must be positive")]
+    fn zero_range_is_rejected() {
+        red(0.0);
+    }
+
+    #[test]
+    fn shaders_declare_the_light_list_for_replacement() {
+        for source in [include_str!("shader.wgsl"), include_str!("light.wgsl")] {
+            assert_eq!(source.matches(LightList::STORAGE_DECLARATION).count(), 1);
+        }
+    }
+}
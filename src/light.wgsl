@@ -0,0 +1,83 @@
+struct CameraUniform {
+    view_position: vec4<f32>,
+    view_proj: mat4x4<f32>,
+    view: mat4x4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct Light {
+    position: vec3<f32>,
+    ambient_strength: f32,
+    color: vec3<f32>,
+    kind: u32,
+    direction: vec3<f32>,
+    inner_cutoff: f32,
+    outer_cutoff: f32,
+};
+
+const LIGHT_DIRECTIONAL: u32 = 1u;
+
+@group(1) @binding(0)
+var<uniform> light: Light;
+
+// Lights besides the main one, with the shadow casting point lights first.
+struct ListLight {
+    position: vec3<f32>,
+    range: f32,
+    color: vec3<f32>,
+    kind: u32,
+    direction: vec3<f32>,
+    inner_cutoff: f32,
+    outer_cutoff: f32,
+};
+
+struct LightCount {
+    count: u32,
+    shadowed: u32,
+};
+
+@group(1) @binding(1)
+var<uniform> light_count: LightCount;
+@group(1) @binding(2)
+var<storage, read> lights: array<ListLight>;
+
+struct VertexInput{
+    @location(0) position: vec3<f32>,
+}
+
+struct VertexOutput{
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+}
+
+@vertex
+
+// Instance 0 marks the main light, the rest mark the lights in the list.
+// Directional lights have no position, so their markers collapse to nothing.
+fn vs_main(model: VertexInput, @builtin(instance_index) instance: u32) -> VertexOutput{
+    var scale = 0.25;
+
+    var position = light.position;
+    var color = light.color;
+    if instance > 0u {
+        let listed = lights[instance - 1u];
+        position = listed.position;
+        color = listed.color;
+        if listed.kind == LIGHT_DIRECTIONAL {
+            scale = 0.0;
+        }
+    }
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(model.position * scale + position, 1.0);
+    out.color = color;
+    return out;
+}
+
+@fragment
+
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32>{
+    return vec4<f32>(in.color, 1.0);
+}
@@ -1,15 +1,42 @@
-mod camera;
-mod camera_controller;
-mod instance;
-mod model;
-mod swapchain;
-mod texture;
-mod triangle_model;
-mod vertex_buffer;
-mod window;
-
+use hello_wgpu::{clock::FrameTime, settings::Settings, swapchain::State, window::run};
 use pollster::block_on;
-use window::run;
+
 fn main() {
-    block_on(run())
+    env_logger::init();
+
+    let settings = match Settings::from_env_and_args() {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    if settings.list_adapters {
+        list_adapters(&settings);
+    } else if let Some(output) = &settings.headless_output {
+        if let Err(e) = block_on(run_headless(output, &settings)) {
+            eprintln!("headless render failed: {}", e);
+            std::process::exit(1);
+        }
+    } else {
+        block_on(run(settings))
+    }
+}
+
+fn list_adapters(settings: &Settings) {
+    let instance = settings.adapter.create_instance();
+    for adapter in instance.enumerate_adapters(settings.adapter.backends) {
+        let info = adapter.get_info();
+        println!("{} ({:?}, {:?})", info.name, info.backend, info.device_type);
+    }
+}
+
+async fn run_headless(output: &str, settings: &Settings) -> anyhow::Result<()> {
+    let (width, height) = settings.headless_size;
+    let mut state = State::new_headless(width, height, settings).await?;
+    state.update(FrameTime::default());
+    state.render()?;
+    state.save_frame(output)?;
+    Ok(())
 }
@@ -0,0 +1,387 @@
+use std::collections::HashMap;
+
+use image::{ImageBuffer, Rgba32FImage, RgbaImage};
+
+/// Formats whose mip levels are rendered on the GPU. Anything else has its
+/// levels built on the CPU with [`generate_mips`].
+const GPU_FORMATS: &[wgpu::TextureFormat] = &[
+    wgpu::TextureFormat::Rgba8Unorm,
+    wgpu::TextureFormat::Rgba8UnormSrgb,
+    wgpu::TextureFormat::Rgba16Float,
+];
+
+/// Levels in a full mip chain for a texture this size, down to 1x1.
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Fills in mip levels by drawing each level from the one above it with a
+/// linear filter. Rendering to an sRGB view blends in linear space, so mips
+/// of color textures don't darken.
+pub struct MipmapGenerator {
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
+}
+
+impl MipmapGenerator {
+    /// The GL backend can't sample views that start below the first mip
+    /// level, so there every format falls back to the CPU.
+    pub fn new(device: &wgpu::Device, backend: wgpu::Backend) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("mipmap bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("mipmap sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("mipmap pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("mipmap shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("mipmap.wgsl").into()),
+        });
+
+        let formats = match backend {
+            wgpu::Backend::Gl => &[],
+            _ => GPU_FORMATS,
+        };
+        let pipelines = formats
+            .iter()
+            .map(|&format| {
+                let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("mipmap pipeline"),
+                    layout: Some(&layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vs_main",
+                        buffers: &[],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fs_main",
+                        targets: &[Some(format.into())],
+                    }),
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                });
+                (format, pipeline)
+            })
+            .collect();
+
+        Self {
+            bind_group_layout,
+            sampler,
+            pipelines,
+        }
+    }
+
+    /// Whether [`Self::generate`] can render mips for `format` textures.
+    pub fn supports(&self, format: wgpu::TextureFormat) -> bool {
+        self.pipelines.contains_key(&format)
+    }
+
+    /// Renders every mip level of every layer of `texture` from its level 0,
+    /// which must already be uploaded. The texture needs `RENDER_ATTACHMENT`
+    /// usage and a format this [`Self::supports`].
+    pub fn generate(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+        format: wgpu::TextureFormat,
+    ) {
+        let pipeline = self
+            .pipelines
+            .get(&format)
+            .unwrap_or_else(|| panic!("no mipmap pipeline for {:?}", format));
+
+        let level_view = |layer, level| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("mip level view"),
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_mip_level: level,
+                mip_level_count: std::num::NonZeroU32::new(1),
+                base_array_layer: layer,
+                array_layer_count: std::num::NonZeroU32::new(1),
+                ..Default::default()
+            })
+        };
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("mipmap encoder"),
+        });
+
+        for layer in 0..texture.depth_or_array_layers() {
+            for level in 1..texture.mip_level_count() {
+                let source = level_view(layer, level - 1);
+                let target = level_view(layer, level);
+
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("mipmap bind group"),
+                    layout: &self.bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&source),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&self.sampler),
+                        },
+                    ],
+                });
+
+                let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("mipmap pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &target,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: true,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                });
+                pass.set_pipeline(pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.draw(0..3, 0..1);
+            }
+        }
+
+        queue.submit(Some(encoder.finish()));
+    }
+}
+
+/// Every level below `image` in its mip chain, each half the size of the
+/// one before it down to 1x1. Color channels of `srgb` images are averaged
+/// in linear space, like the GPU path does.
+pub fn generate_mips(image: &RgbaImage, srgb: bool) -> Vec<RgbaImage> {
+    mip_chain(image, |level| downsample(level, srgb))
+}
+
+/// [`generate_mips`] for floating point images, which are already linear.
+pub fn generate_hdr_mips(image: &Rgba32FImage) -> Vec<Rgba32FImage> {
+    mip_chain(image, downsample_hdr)
+}
+
+/// Every level of a cube map's mip chain, starting with `faces` themselves.
+/// Faces are filtered on their own, without blending across their edges.
+pub fn generate_cube_mips(faces: &[Rgba32FImage; 6]) -> Vec<[Rgba32FImage; 6]> {
+    let mut levels = vec![faces.clone()];
+    while levels.last().unwrap()[0].width() > 1 {
+        let next = levels.last().unwrap().each_ref().map(downsample_hdr);
+        levels.push(next);
+    }
+    levels
+}
+
+fn mip_chain<P: image::Pixel>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    downsample: impl Fn(&ImageBuffer<P, Vec<P::Subpixel>>) -> ImageBuffer<P, Vec<P::Subpixel>>,
+) -> Vec<ImageBuffer<P, Vec<P::Subpixel>>> {
+    let mut mips: Vec<ImageBuffer<P, Vec<P::Subpixel>>> = Vec::new();
+    let mut level = image;
+    while level.width() > 1 || level.height() > 1 {
+        mips.push(downsample(level));
+        level = mips.last().unwrap();
+    }
+    mips
+}
+
+/// `image` at half its size, rounded down but at least 1, with each pixel
+/// the average of the 2x2 pixels it covers. The last row or column of odd
+/// sized images is repeated.
+pub fn downsample(image: &RgbaImage, srgb: bool) -> RgbaImage {
+    let decode: [f32; 256] = std::array::from_fn(|value| {
+        let value = value as f32 / 255.0;
+        if srgb {
+            srgb_to_linear(value)
+        } else {
+            value
+        }
+    });
+
+    let (width, height) = image.dimensions();
+    RgbaImage::from_fn((width / 2).max(1), (height / 2).max(1), |x, y| {
+        let average = average_2x2(image.dimensions(), x, y, |sx, sy| {
+            let pixel = image.get_pixel(sx, sy).0;
+            [
+                decode[pixel[0] as usize],
+                decode[pixel[1] as usize],
+                decode[pixel[2] as usize],
+                pixel[3] as f32 / 255.0,
+            ]
+        });
+
+        image::Rgba(std::array::from_fn(|channel| {
+            let mut value = average[channel];
+            if srgb && channel < 3 {
+                value = linear_to_srgb(value);
+            }
+            (value * 255.0).round().clamp(0.0, 255.0) as u8
+        }))
+    })
+}
+
+/// [`downsample`] for floating point images.
+pub fn downsample_hdr(image: &Rgba32FImage) -> Rgba32FImage {
+    let (width, height) = image.dimensions();
+    Rgba32FImage::from_fn((width / 2).max(1), (height / 2).max(1), |x, y| {
+        image::Rgba(average_2x2(image.dimensions(), x, y, |sx, sy| {
+            image.get_pixel(sx, sy).0
+        }))
+    })
+}
+
+/// Average of the up to 2x2 texels of an image `width` by `height` that
+/// pixel `x`, `y` of the next mip level covers.
+fn average_2x2(
+    (width, height): (u32, u32),
+    x: u32,
+    y: u32,
+    texel: impl Fn(u32, u32) -> [f32; 4],
+) -> [f32; 4] {
+    let xs = [2 * x, (2 * x + 1).min(width - 1)];
+    let ys = [2 * y, (2 * y + 1).min(height - 1)];
+
+    let mut sum = [0.0f32; 4];
+    for &sy in &ys {
+        for &sx in &xs {
+            for (sum, value) in sum.iter_mut().zip(texel(sx, sy)) {
+                *sum += value;
+            }
+        }
+    }
+    sum.map(|sum| sum / 4.0)
+}
+
+pub(crate) fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sizes<P: image::Pixel>(mips: &[ImageBuffer<P, Vec<P::Subpixel>>]) -> Vec<(u32, u32)> {
+        mips.iter().map(ImageBuffer::dimensions).collect()
+    }
+
+    #[test]
+    fn chain_reaches_1x1_and_matches_level_count() {
+        for (width, height) in [(1, 1), (5, 3), (256, 256)] {
+            let mips = generate_mips(&RgbaImage::new(width, height), false);
+            assert_eq!(mips.len() as u32 + 1, mip_level_count(width, height));
+            if let Some(last) = mips.last() {
+                assert_eq!(last.dimensions(), (1, 1));
+            }
+        }
+
+        let mips = generate_mips(&RgbaImage::new(5, 3), false);
+        assert_eq!(sizes(&mips), [(2, 1), (1, 1)]);
+        assert_eq!(generate_mips(&RgbaImage::new(256, 256), true).len(), 8);
+    }
+
+    #[test]
+    fn downsample_averages_2x2() {
+        let image = RgbaImage::from_vec(
+            2,
+            2,
+            vec![
+                0, 10, 100, 255, 40, 20, 100, 255, //
+                80, 30, 200, 0, 120, 40, 200, 0,
+            ],
+        )
+        .unwrap();
+        let mip = downsample(&image, false);
+        assert_eq!(mip.dimensions(), (1, 1));
+        assert_eq!(mip.get_pixel(0, 0).0, [60, 25, 150, 128]);
+
+        let image = Rgba32FImage::from_vec(
+            2,
+            2,
+            vec![
+                0.0, 1.0, 4.0, 1.0, 1.0, 1.0, 4.0, 1.0, //
+                2.0, 1.0, 8.0, 0.0, 3.0, 1.0, 8.0, 0.0,
+            ],
+        )
+        .unwrap();
+        let mip = downsample_hdr(&image);
+        assert_eq!(mip.dimensions(), (1, 1));
+        assert_eq!(mip.get_pixel(0, 0).0, [1.5, 1.0, 6.0, 0.5]);
+    }
+
+    #[test]
+    fn single_rows_and_columns_are_repeated() {
+        // A column one texel wide is averaged with itself, so only its two
+        // rows contribute.
+        let image = RgbaImage::from_vec(1, 2, vec![0, 0, 0, 0, 200, 100, 50, 255]).unwrap();
+        let mip = downsample(&image, false);
+        assert_eq!(mip.dimensions(), (1, 1));
+        assert_eq!(mip.get_pixel(0, 0).0, [100, 50, 25, 128]);
+
+        let image =
+            Rgba32FImage::from_vec(2, 1, vec![1.0; 4].into_iter().chain([3.0; 4]).collect())
+                .unwrap();
+        let mip = downsample_hdr(&image);
+        assert_eq!(mip.get_pixel(0, 0).0, [2.0; 4]);
+    }
+
+    #[test]
+    fn srgb_checker_averages_in_linear_space() {
+        let image = RgbaImage::from_fn(2, 2, |x, y| {
+            let value = if (x + y) % 2 == 0 { 0 } else { 255 };
+            image::Rgba([value, value, value, 255])
+        });
+
+        // Half of full intensity in linear light, re-encoded as sRGB.
+        let srgb = downsample(&image, true).get_pixel(0, 0).0;
+        assert_eq!(srgb, [188, 188, 188, 255]);
+        assert_ne!(srgb[0], 128);
+
+        let linear = downsample(&image, false).get_pixel(0, 0).0;
+        assert_eq!(linear, [128, 128, 128, 255]);
+    }
+}
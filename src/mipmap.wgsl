@@ -0,0 +1,28 @@
+struct VertexOutput{
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+}
+
+// One triangle covering the whole target, no vertex buffer needed.
+@vertex
+
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput{
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.tex_coords = uv;
+    return out;
+}
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+
+// Sampling halfway between four texels of the level above averages them.
+@fragment
+
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32>{
+    return textureSample(t_source, s_source, in.tex_coords);
+}
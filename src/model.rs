@@ -1,13 +1,21 @@
 use std::{
+    cell::RefCell,
+    collections::HashMap,
     io::{BufReader, Cursor},
     mem::size_of,
     ops::Range,
+    sync::Arc,
 };
 
-use anyhow::Ok;
+use cgmath::InnerSpace;
 use wgpu::util::DeviceExt;
 
-use crate::texture;
+use crate::{
+    assets,
+    error::{Error, Result},
+    gltf_loader, texture,
+    texture_cache::TextureCache,
+};
 pub trait Vertex {
     fn desc<'a>() -> wgpu::VertexBufferLayout<'a>;
 }
@@ -18,6 +26,11 @@ pub struct ModelVertex {
     pub position: [f32; 3],
     pub tex_coords: [f32; 2],
     pub normal: [f32; 3],
+    pub tangent: [f32; 3],
+    pub bitangent: [f32; 3],
+    /// Linear vertex color, multiplied with the diffuse color. White for
+    /// models without vertex colors.
+    pub color: [f32; 3],
 }
 
 impl Vertex for ModelVertex {
@@ -41,16 +54,244 @@ impl Vertex for ModelVertex {
                     shader_location: 2,
                     format: wgpu::VertexFormat::Float32x3,
                 },
+                wgpu::VertexAttribute {
+                    offset: size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: size_of::<[f32; 11]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                // Locations 5 to 11 are taken by the instance attributes.
+                wgpu::VertexAttribute {
+                    offset: size_of::<[f32; 14]>() as wgpu::BufferAddress,
+                    shader_location: 12,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
             ],
         }
     }
 }
+
+/// The metallic-roughness parameters of a material, laid out to match
+/// `Material` in the shader. Each factor is multiplied with its texture.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+    /// Linear base color and alpha.
+    pub base_color: [f32; 4],
+    /// Linear color the surface glows with, unaffected by lighting.
+    pub emissive: [f32; 3],
+    /// 0 for dielectrics, 1 for metals.
+    pub metallic: f32,
+    /// Perceptual roughness, squared for the GGX lobe's width.
+    pub roughness: f32,
+    /// How much of the occlusion texture darkens the ambient light.
+    pub occlusion_strength: f32,
+    /// Scale of the normal map's X and Y.
+    pub normal_scale: f32,
+    /// `illum`: 0 is unlit color, 1 drops the specular term, 2 and up are
+    /// fully lit.
+    pub illumination_model: u32,
+}
+
+impl MaterialUniform {
+    /// Reads the PBR extension's `Pr`, `Pm` and `Ke`. Materials without
+    /// them are converted the way Blender imports them: roughness from
+    /// `Ns` and no metal. Materials parsed by [`Model::load_model`] have a
+    /// white `Kd` unless they set one.
+    pub fn from_obj(m: &tobj::Material) -> Self {
+        let [r, g, b] = m.diffuse;
+        let roughness = mtl_param(m, "Pr")
+            .map(|[r]| r)
+            .unwrap_or_else(|| 1.0 - (m.shininess.clamp(0.0, 1000.0) / 1000.0).sqrt());
+
+        Self {
+            base_color: [r, g, b, m.dissolve],
+            emissive: mtl_param(m, "Ke").unwrap_or([0.0; 3]),
+            metallic: mtl_param(m, "Pm").map(|[m]| m).unwrap_or(0.0),
+            roughness,
+            occlusion_strength: 1.0,
+            normal_scale: 1.0,
+            illumination_model: m.illumination_model.unwrap_or(2).into(),
+        }
+    }
+
+    pub fn from_gltf(material: &gltf::Material) -> Self {
+        let pbr = material.pbr_metallic_roughness();
+        Self {
+            base_color: pbr.base_color_factor(),
+            emissive: material.emissive_factor(),
+            metallic: pbr.metallic_factor(),
+            roughness: pbr.roughness_factor(),
+            occlusion_strength: material.occlusion_texture().map_or(1.0, |o| o.strength()),
+            normal_scale: material.normal_texture().map_or(1.0, |n| n.scale()),
+            illumination_model: 2,
+        }
+    }
+}
+
+impl Default for MaterialUniform {
+    fn default() -> Self {
+        Self {
+            base_color: [1.0; 4],
+            emissive: [0.0; 3],
+            metallic: 0.0,
+            roughness: 0.5,
+            occlusion_strength: 1.0,
+            normal_scale: 1.0,
+            illumination_model: 2,
+        }
+    }
+}
+
+/// The maps of a material, in binding order. Maps a material doesn't have
+/// are 1x1 textures that leave its factors as they are.
+#[derive(Clone)]
+pub struct MaterialTextures {
+    /// sRGB base color, with alpha.
+    pub base_color: Arc<texture::Texture>,
+    /// Tangent space normals.
+    pub normal: Arc<texture::Texture>,
+    /// Roughness in the green channel, as glTF packs it with metallic.
+    pub roughness: Arc<texture::Texture>,
+    /// Metallic in the blue channel.
+    pub metallic: Arc<texture::Texture>,
+    /// Ambient occlusion in the red channel.
+    pub occlusion: Arc<texture::Texture>,
+    /// sRGB emitted color.
+    pub emissive: Arc<texture::Texture>,
+}
+
+impl MaterialTextures {
+    /// White maps and a flat normal map, for a material that is just its
+    /// factors.
+    pub fn plain(textures: &mut TextureCache, device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let white = textures.white(device, queue);
+        Self {
+            base_color: white.clone(),
+            normal: textures.flat_normal(device, queue),
+            roughness: white.clone(),
+            metallic: white.clone(),
+            occlusion: white.clone(),
+            emissive: white,
+        }
+    }
+
+    fn all(&self) -> [&texture::Texture; 6] {
+        [
+            &self.base_color,
+            &self.normal,
+            &self.roughness,
+            &self.metallic,
+            &self.occlusion,
+            &self.emissive,
+        ]
+    }
+}
+
 pub struct Material {
     pub name: String,
-    pub diffuse_texture: texture::Texture,
+    pub textures: MaterialTextures,
+    pub uniform: MaterialUniform,
+    pub buffer: wgpu::Buffer,
     pub bind_group: wgpu::BindGroup,
 }
 
+impl Material {
+    /// Each texture and its sampler take two bindings, in
+    /// [`MaterialTextures`] order, followed by the uniform.
+    pub const UNIFORM_BINDING: u32 = 12;
+
+    pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let mut entries = Vec::new();
+        for binding in (0..Self::UNIFORM_BINDING).step_by(2) {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            });
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: binding + 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            });
+        }
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: Self::UNIFORM_BINDING,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        });
+
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("material bind group layout"),
+            entries: &entries,
+        })
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        name: &str,
+        textures: MaterialTextures,
+        uniform: MaterialUniform,
+        layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{name} material buffer")),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let mut entries = Vec::new();
+        for (binding, texture) in (0..).step_by(2).zip(textures.all()) {
+            entries.push(wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: binding + 1,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            });
+        }
+        entries.push(wgpu::BindGroupEntry {
+            binding: Self::UNIFORM_BINDING,
+            resource: buffer.as_entire_binding(),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &entries,
+            label: Some(name),
+        });
+
+        Self {
+            name: name.to_string(),
+            textures,
+            uniform,
+            buffer,
+            bind_group,
+        }
+    }
+
+    /// Uploads `uniform` after its parameters have been changed.
+    pub fn write(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+}
+
 pub struct Mesh {
     pub name: String,
     pub vertex_buffer: wgpu::Buffer,
@@ -65,17 +306,42 @@ pub struct Model {
 }
 
 impl Model {
+    /// Loads a model from the cache's assets, as glTF for `.gltf` and `.glb`
+    /// files and as OBJ otherwise. Files it references are looked up relative
+    /// to it, and its textures are shared through `textures`.
     pub async fn load_model(
         file_name: &str,
+        textures: &mut TextureCache,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         layout: &wgpu::BindGroupLayout,
-    ) -> anyhow::Result<Model> {
-        let binding = String::from("res/".to_owned() + file_name);
-        let obj_text = std::fs::read_to_string(&binding)?;
+    ) -> Result<Model> {
+        let extension = std::path::Path::new(file_name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("gltf" | "glb") => gltf_loader::load(file_name, textures, device, queue, layout),
+            _ => Self::load_obj(file_name, textures, device, queue, layout).await,
+        }
+    }
+
+    async fn load_obj(
+        file_name: &str,
+        textures: &mut TextureCache,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+    ) -> Result<Model> {
+        let assets = textures.assets();
+        let obj_text = assets.read_to_string(file_name)?;
         let obj_cursor = Cursor::new(obj_text);
         let mut obj_reader = BufReader::new(obj_cursor);
 
+        // tobj only lets the material loader report a bare `LoadError`, so keep
+        // the asset error around to report which file was missing.
+        let mtl_error = RefCell::new(None);
+
         let (models, obj_materials) = tobj::load_obj_buf_async(
             &mut obj_reader,
             &tobj::LoadOptions {
@@ -83,59 +349,81 @@ impl Model {
                 triangulate: true,
                 ..Default::default()
             },
-            |p| async move {
-                let binding = String::from("res/".to_owned() + &p);
-                let mat_text = std::fs::read_to_string(binding).unwrap();
-                tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))
+            |p| {
+                let mtl_error = &mtl_error;
+                async move {
+                    let path = assets::resolve(file_name, &p);
+                    let mat_text = assets.read_to_string(&path).map_err(|e| {
+                        *mtl_error.borrow_mut() = Some(e);
+                        tobj::LoadError::OpenFileFailed
+                    })?;
+                    let mat_text = default_diffuse(&mat_text);
+                    tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))
+                }
             },
         )
-        .await?;
+        .await
+        .map_err(|source| Error::ObjParse {
+            path: file_name.to_string(),
+            source,
+        })?;
+
+        if let Some(e) = mtl_error.into_inner() {
+            return Err(e);
+        }
+
+        let obj_materials = obj_materials.map_err(|source| Error::ObjParse {
+            path: file_name.to_string(),
+            source,
+        })?;
 
         let mut materials: Vec<Material> = Vec::new();
 
-        for m in obj_materials? {
-            let diffuse_texture = texture::Texture::new(device, queue, &m.diffuse_texture);
+        // Maps a material doesn't have leave its factors as they are.
+        let plain = MaterialTextures::plain(textures, device, queue);
+        for m in obj_materials {
+            // tobj keeps the PBR extension's statements as unknown parameters.
+            let extension = |statement| m.unknown_param.get(statement).map_or("", String::as_str);
+            let normal_map = match m.normal_texture.as_str() {
+                "" => extension("norm"),
+                map => map,
+            };
+            let mut load = |statement, map, fallback: &Arc<texture::Texture>| {
+                load_mtl_map(textures, device, queue, file_name, statement, map)
+                    .map(|texture| texture.unwrap_or_else(|| fallback.clone()))
+            };
 
-            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-                layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                    },
-                ],
-                label: None,
-            });
+            let material_textures = MaterialTextures {
+                base_color: load("map_Kd", &m.diffuse_texture, &plain.base_color)?,
+                normal: load("norm", normal_map, &plain.normal)?,
+                roughness: load("map_Pr", extension("map_Pr"), &plain.roughness)?,
+                metallic: load("map_Pm", extension("map_Pm"), &plain.metallic)?,
+                occlusion: plain.occlusion.clone(),
+                emissive: load("map_Ke", extension("map_Ke"), &plain.emissive)?,
+            };
 
-            materials.push(Material {
-                name: m.name,
-                diffuse_texture,
-                bind_group,
-            })
+            materials.push(Material::new(
+                device,
+                &m.name,
+                material_textures,
+                MaterialUniform::from_obj(&m),
+                layout,
+            ));
         }
 
+        // Meshes without a usable `usemtl` get a plain white material, only
+        // added when something needs it.
+        let mut default_material = None;
+        let material_count = materials.len();
+
         let meshes = models
             .into_iter()
             .map(|m| {
-                let vertices = (0..m.mesh.positions.len() / 3)
-                    .map(|i| ModelVertex {
-                        position: [
-                            m.mesh.positions[i * 3],
-                            m.mesh.positions[i * 3 + 1],
-                            m.mesh.positions[i * 3 + 2],
-                        ],
-                        tex_coords: [m.mesh.texcoords[i * 2], m.mesh.texcoords[i * 2 + 1]],
-                        normal: [
-                            m.mesh.normals[i * 3],
-                            m.mesh.normals[i * 3 + 1],
-                            m.mesh.normals[i * 3 + 2],
-                        ],
-                    })
-                    .collect::<Vec<_>>();
+                let (vertices, indices) = build_vertices(&m.mesh, SMOOTHING_ANGLE);
+                let material = match m.mesh.material_id {
+                    Some(id) if id < material_count => id,
+                    _ => *default_material.get_or_insert(material_count),
+                };
 
                 let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                     label: Some(&format!("{:?} Vertex Buffer", file_name)),
@@ -144,24 +432,261 @@ impl Model {
                 });
                 let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                     label: Some(&format!("{:?} Index Buffer", file_name)),
-                    contents: bytemuck::cast_slice(&m.mesh.indices),
+                    contents: bytemuck::cast_slice(&indices),
                     usage: wgpu::BufferUsages::INDEX,
                 });
 
                 Mesh {
-                    name: file_name.to_string(),
+                    name: m.name,
                     vertex_buffer,
                     index_buffer,
-                    num_elements: m.mesh.indices.len() as u32,
-                    material: m.mesh.material_id.unwrap_or(0),
+                    num_elements: indices.len() as u32,
+                    material,
                 }
             })
             .collect::<Vec<_>>();
 
+        if default_material.is_some() {
+            materials.push(Material::new(
+                device,
+                "default material",
+                plain,
+                MaterialUniform::default(),
+                layout,
+            ));
+        }
+
         Ok(Model { meshes, materials })
     }
 }
 
+/// The kind of texture an MTL map statement refers to. Only the color maps
+/// are authored in sRGB; everything else holds linear values.
+pub fn mtl_map_kind(statement: &str) -> texture::TextureKind {
+    match statement {
+        "map_Ka" | "map_Kd" | "map_Ks" | "map_Ke" => texture::TextureKind::Color,
+        "map_Bump" | "map_bump" | "bump" | "norm" => texture::TextureKind::Normal,
+        _ => texture::TextureKind::Data,
+    }
+}
+
+/// The texture an MTL `statement` names with `map`, resolved relative to the
+/// OBJ file, or `None` if the material has no such map.
+fn load_mtl_map(
+    textures: &mut TextureCache,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    file_name: &str,
+    statement: &str,
+    map: &str,
+) -> Result<Option<Arc<texture::Texture>>> {
+    if map.is_empty() {
+        return Ok(None);
+    }
+
+    let path = assets::resolve(file_name, map);
+    let sampler = textures.sampler();
+    textures
+        .load(device, queue, &path, mtl_map_kind(statement), sampler)
+        .map(Some)
+}
+
+/// Adds `Kd 1 1 1` to the start of every material in `mtl`. tobj reads a
+/// missing `Kd` as black, where the MTL spec and other loaders use white;
+/// a `Kd` the material does set comes later and overrides it.
+fn default_diffuse(mtl: &str) -> String {
+    let mut text = String::with_capacity(mtl.len());
+    for line in mtl.lines() {
+        text.push_str(line);
+        text.push('\n');
+        if line.split_whitespace().next() == Some("newmtl") {
+            text.push_str("Kd 1 1 1\n");
+        }
+    }
+    text
+}
+
+/// A numeric MTL statement tobj doesn't parse itself, such as the PBR
+/// extension's `Pr`, or `None` if it is missing or doesn't have `N` numbers.
+fn mtl_param<const N: usize>(m: &tobj::Material, statement: &str) -> Option<[f32; N]> {
+    let values = m.unknown_param.get(statement)?.split_whitespace();
+    let values: Vec<f32> = values
+        .map(str::parse)
+        .collect::<std::result::Result<_, _>>()
+        .ok()?;
+    values.try_into().ok()
+}
+
+/// Largest angle in degrees between two faces of an OBJ without normals for
+/// their shared vertices to be smoothed.
+pub const SMOOTHING_ANGLE: f32 = 60.0;
+
+/// Turns a triangulated, single-index OBJ mesh into vertices and indices.
+/// Texture coordinates default to zero and vertex colors to white when the
+/// file has none, and missing normals are generated with
+/// [`generate_normals`].
+pub fn build_vertices(mesh: &tobj::Mesh, smoothing_angle: f32) -> (Vec<ModelVertex>, Vec<u32>) {
+    let triple = |values: &[f32], i: usize| -> Option<[f32; 3]> {
+        Some([
+            *values.get(i * 3)?,
+            *values.get(i * 3 + 1)?,
+            *values.get(i * 3 + 2)?,
+        ])
+    };
+
+    let vertices = (0..mesh.positions.len() / 3)
+        .map(|i| ModelVertex {
+            position: triple(&mesh.positions, i).unwrap(),
+            tex_coords: match (mesh.texcoords.get(i * 2), mesh.texcoords.get(i * 2 + 1)) {
+                (Some(&u), Some(&v)) => [u, v],
+                _ => [0.0; 2],
+            },
+            normal: triple(&mesh.normals, i).unwrap_or([0.0; 3]),
+            tangent: [0.0; 3],
+            bitangent: [0.0; 3],
+            color: triple(&mesh.vertex_color, i).unwrap_or([1.0; 3]),
+        })
+        .collect::<Vec<_>>();
+
+    let (mut vertices, indices) = if mesh.normals.is_empty() {
+        generate_normals(&vertices, &mesh.indices, smoothing_angle)
+    } else {
+        (vertices, mesh.indices.clone())
+    };
+
+    compute_tangents(&mut vertices, &indices);
+    (vertices, indices)
+}
+
+/// Gives every triangle corner the area weighted average normal of the faces
+/// around its position that are within `smoothing_angle` degrees of its own
+/// face, so 0 gives flat shading and 180 smooths everything. Vertices are
+/// split along hard edges and merged again where they end up identical.
+pub fn generate_normals(
+    vertices: &[ModelVertex],
+    indices: &[u32],
+    smoothing_angle: f32,
+) -> (Vec<ModelVertex>, Vec<u32>) {
+    let triangles = indices.chunks_exact(3).collect::<Vec<_>>();
+
+    let face_normals = triangles
+        .iter()
+        .map(|c| {
+            let pos0: cgmath::Vector3<f32> = vertices[c[0] as usize].position.into();
+            let pos1: cgmath::Vector3<f32> = vertices[c[1] as usize].position.into();
+            let pos2: cgmath::Vector3<f32> = vertices[c[2] as usize].position.into();
+            (pos1 - pos0).cross(pos2 - pos0)
+        })
+        .collect::<Vec<_>>();
+
+    // Faces touching each position, regardless of texture seams.
+    let position_key = |i: u32| vertices[i as usize].position.map(f32::to_bits);
+    let mut faces_at = HashMap::<[u32; 3], Vec<usize>>::new();
+    for (face, c) in triangles.iter().enumerate() {
+        for &i in *c {
+            faces_at.entry(position_key(i)).or_default().push(face);
+        }
+    }
+
+    let min_cos = smoothing_angle.to_radians().cos();
+    let mut welded = HashMap::<Vec<u8>, u32>::new();
+    let mut out_vertices = Vec::new();
+    let mut out_indices = Vec::with_capacity(indices.len());
+
+    for (face, c) in triangles.iter().enumerate() {
+        let own = face_normals[face];
+        for &i in *c {
+            let mut normal = cgmath::Vector3::new(0.0, 0.0, 0.0);
+            for &other in &faces_at[&position_key(i)] {
+                let candidate = face_normals[other];
+                let dot = own.dot(candidate);
+                // Compare cosines without normalizing: dot >= |a||b|cos.
+                if other == face || dot >= min_cos * own.magnitude() * candidate.magnitude() {
+                    normal += candidate;
+                }
+            }
+
+            let mut vertex = vertices[i as usize];
+            if normal.magnitude2() > 0.0 {
+                vertex.normal = normal.normalize().into();
+            }
+
+            let index = *welded
+                .entry(bytemuck::bytes_of(&vertex).to_vec())
+                .or_insert_with(|| {
+                    out_vertices.push(vertex);
+                    out_vertices.len() as u32 - 1
+                });
+            out_indices.push(index);
+        }
+    }
+
+    (out_vertices, out_indices)
+}
+
+/// Fills in per-vertex tangents and bitangents from the triangle UVs, averaging
+/// over every triangle a vertex belongs to.
+pub(crate) fn compute_tangents(vertices: &mut [ModelVertex], indices: &[u32]) {
+    let mut triangles_included = vec![0u32; vertices.len()];
+
+    for c in indices.chunks_exact(3) {
+        let v0 = vertices[c[0] as usize];
+        let v1 = vertices[c[1] as usize];
+        let v2 = vertices[c[2] as usize];
+
+        let pos0: cgmath::Vector3<f32> = v0.position.into();
+        let pos1: cgmath::Vector3<f32> = v1.position.into();
+        let pos2: cgmath::Vector3<f32> = v2.position.into();
+
+        let uv0: cgmath::Vector2<f32> = v0.tex_coords.into();
+        let uv1: cgmath::Vector2<f32> = v1.tex_coords.into();
+        let uv2: cgmath::Vector2<f32> = v2.tex_coords.into();
+
+        let delta_pos1 = pos1 - pos0;
+        let delta_pos2 = pos2 - pos0;
+        let delta_uv1 = uv1 - uv0;
+        let delta_uv2 = uv2 - uv0;
+
+        let det = delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x;
+        if det.abs() < f32::EPSILON {
+            continue;
+        }
+
+        let r = 1.0 / det;
+        let tangent = (delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) * r;
+        // Texture rows run top to bottom while v runs bottom to top, so the
+        // bitangent is flipped to match the normal map's green channel.
+        let bitangent = (delta_pos2 * delta_uv1.x - delta_pos1 * delta_uv2.x) * -r;
+
+        for &i in c {
+            let vertex = &mut vertices[i as usize];
+            vertex.tangent = (tangent + cgmath::Vector3::from(vertex.tangent)).into();
+            vertex.bitangent = (bitangent + cgmath::Vector3::from(vertex.bitangent)).into();
+            triangles_included[i as usize] += 1;
+        }
+    }
+
+    for (vertex, &n) in vertices.iter_mut().zip(&triangles_included) {
+        if n > 0 {
+            let denom = 1.0 / n as f32;
+            vertex.tangent = (cgmath::Vector3::from(vertex.tangent) * denom).into();
+            vertex.bitangent = (cgmath::Vector3::from(vertex.bitangent) * denom).into();
+        } else {
+            // Without usable UVs any basis around the normal will do, as long
+            // as the shader has something to normalize.
+            let normal = cgmath::Vector3::from(vertex.normal);
+            let axis = if normal.x.abs() < 0.9 {
+                cgmath::Vector3::unit_x()
+            } else {
+                cgmath::Vector3::unit_y()
+            };
+            let tangent = normal.cross(axis).normalize();
+            vertex.tangent = tangent.into();
+            vertex.bitangent = normal.cross(tangent).into();
+        }
+    }
+}
+
 // model.rs
 pub trait DrawModel<'a> {
     fn draw_mesh(
@@ -169,6 +694,7 @@ pub trait DrawModel<'a> {
         mesh: &'a Mesh,
         material: &'a Material,
         camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
     );
     fn draw_mesh_instanced(
         &mut self,
@@ -176,6 +702,21 @@ pub trait DrawModel<'a> {
         material: &'a Material,
         instances: Range<u32>,
         camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    );
+
+    fn draw_model(
+        &mut self,
+        model: &'a Model,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    );
+    fn draw_model_instanced(
+        &mut self,
+        model: &'a Model,
+        instances: Range<u32>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
     );
 }
 
@@ -188,8 +729,9 @@ where
         mesh: &'a Mesh,
         material: &'a Material,
         camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
     ) {
-        self.draw_mesh_instanced(mesh, material, 0..1, camera_bind_group);
+        self.draw_mesh_instanced(mesh, material, 0..1, camera_bind_group, light_bind_group);
     }
 
     fn draw_mesh_instanced(
@@ -198,11 +740,289 @@ where
         material: &'a Material,
         instances: Range<u32>,
         camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
     ) {
         self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
         self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
         self.set_bind_group(0, &material.bind_group, &[]);
         self.set_bind_group(1, camera_bind_group, &[]);
+        self.set_bind_group(2, light_bind_group, &[]);
+        self.draw_indexed(0..mesh.num_elements, 0, instances);
+    }
+
+    fn draw_model(
+        &mut self,
+        model: &'a Model,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    ) {
+        self.draw_model_instanced(model, 0..1, camera_bind_group, light_bind_group);
+    }
+
+    fn draw_model_instanced(
+        &mut self,
+        model: &'a Model,
+        instances: Range<u32>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    ) {
+        for mesh in &model.meshes {
+            let material = &model.materials[mesh.material];
+            self.draw_mesh_instanced(
+                mesh,
+                material,
+                instances.clone(),
+                camera_bind_group,
+                light_bind_group,
+            );
+        }
+    }
+}
+
+/// Draws a model's geometry as the light source markers, ignoring its
+/// materials. Instance 0 is the main light and the rest are point lights.
+pub trait DrawLight<'a> {
+    fn draw_light_mesh(
+        &mut self,
+        mesh: &'a Mesh,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    );
+    fn draw_light_mesh_instanced(
+        &mut self,
+        mesh: &'a Mesh,
+        instances: Range<u32>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    );
+
+    fn draw_light_model(
+        &mut self,
+        model: &'a Model,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    );
+    fn draw_light_model_instanced(
+        &mut self,
+        model: &'a Model,
+        instances: Range<u32>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    );
+}
+
+impl<'a, 'b> DrawLight<'b> for wgpu::RenderPass<'a>
+where
+    'b: 'a,
+{
+    fn draw_light_mesh(
+        &mut self,
+        mesh: &'a Mesh,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    ) {
+        self.draw_light_mesh_instanced(mesh, 0..1, camera_bind_group, light_bind_group);
+    }
+
+    fn draw_light_mesh_instanced(
+        &mut self,
+        mesh: &'a Mesh,
+        instances: Range<u32>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    ) {
+        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.set_bind_group(0, camera_bind_group, &[]);
+        self.set_bind_group(1, light_bind_group, &[]);
         self.draw_indexed(0..mesh.num_elements, 0, instances);
     }
+
+    fn draw_light_model(
+        &mut self,
+        model: &'a Model,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    ) {
+        self.draw_light_model_instanced(model, 0..1, camera_bind_group, light_bind_group);
+    }
+
+    fn draw_light_model_instanced(
+        &mut self,
+        model: &'a Model,
+        instances: Range<u32>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    ) {
+        for mesh in &model.meshes {
+            self.draw_light_mesh_instanced(
+                mesh,
+                instances.clone(),
+                camera_bind_group,
+                light_bind_group,
+            );
+        }
+    }
+}
+
+/// Draws only a model's geometry, for depth-only passes that set their own
+/// bind groups.
+pub trait DrawGeometry<'a> {
+    fn draw_mesh_geometry_instanced(&mut self, mesh: &'a Mesh, instances: Range<u32>);
+
+    fn draw_model_geometry_instanced(&mut self, model: &'a Model, instances: Range<u32>);
+}
+
+impl<'a, 'b> DrawGeometry<'b> for wgpu::RenderPass<'a>
+where
+    'b: 'a,
+{
+    fn draw_mesh_geometry_instanced(&mut self, mesh: &'a Mesh, instances: Range<u32>) {
+        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.draw_indexed(0..mesh.num_elements, 0, instances);
+    }
+
+    fn draw_model_geometry_instanced(&mut self, model: &'a Model, instances: Range<u32>) {
+        for mesh in &model.meshes {
+            self.draw_mesh_geometry_instanced(mesh, instances.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two triangles folded 90 degrees along the edge from the origin to
+    /// (0, 1, 0): one facing +z, the other +x.
+    const FOLDED: &str = "\
+v 0 0 0
+v 1 0 0
+v 0 1 0
+v 0 0 1
+f 1 2 3
+f 1 3 4
+";
+
+    fn parse_obj(obj: &str) -> tobj::Mesh {
+        let (mut models, _) = tobj::load_obj_buf(
+            &mut BufReader::new(Cursor::new(obj)),
+            &tobj::LoadOptions {
+                single_index: true,
+                triangulate: true,
+                ..Default::default()
+            },
+            |_| Err(tobj::LoadError::OpenFileFailed),
+        )
+        .unwrap();
+        models.remove(0).mesh
+    }
+
+    fn normals_at(vertices: &[ModelVertex], position: [f32; 3]) -> Vec<[f32; 3]> {
+        vertices
+            .iter()
+            .filter(|v| v.position == position)
+            .map(|v| v.normal)
+            .collect()
+    }
+
+    fn assert_close(a: [f32; 3], b: [f32; 3]) {
+        let close = a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-5);
+        assert!(close, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn missing_uvs_and_normals_are_filled_in() {
+        let (vertices, indices) = build_vertices(&parse_obj(FOLDED), SMOOTHING_ANGLE);
+        assert_eq!(indices.len(), 6);
+        for vertex in &vertices {
+            let length = cgmath::Vector3::from(vertex.normal).magnitude();
+            assert!((length - 1.0).abs() < 1e-5, "normal {:?}", vertex.normal);
+            assert_eq!(vertex.tex_coords, [0.0; 2]);
+            assert_eq!(vertex.color, [1.0; 3]);
+        }
+    }
+
+    #[test]
+    fn zero_smoothing_angle_keeps_faces_flat() {
+        let (vertices, _) = build_vertices(&parse_obj(FOLDED), 0.0);
+        // The shared edge is split into one vertex per face.
+        assert_eq!(vertices.len(), 6);
+        let mut normals = normals_at(&vertices, [0.0, 0.0, 0.0]);
+        normals.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(normals.len(), 2);
+        assert_close(normals[0], [0.0, 0.0, 1.0]);
+        assert_close(normals[1], [1.0, 0.0, 0.0]);
+        assert_close(normals_at(&vertices, [1.0, 0.0, 0.0])[0], [0.0, 0.0, 1.0]);
+        assert_close(normals_at(&vertices, [0.0, 0.0, 1.0])[0], [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn full_smoothing_angle_averages_across_the_edge() {
+        let (vertices, _) = build_vertices(&parse_obj(FOLDED), 180.0);
+        assert_eq!(vertices.len(), 4);
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        assert_close(normals_at(&vertices, [0.0, 0.0, 0.0])[0], [half, 0.0, half]);
+        assert_close(normals_at(&vertices, [0.0, 1.0, 0.0])[0], [half, 0.0, half]);
+        assert_close(normals_at(&vertices, [1.0, 0.0, 0.0])[0], [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn duplicated_positions_share_a_normal() {
+        // The second face uses its own copies of the shared edge.
+        let obj = "\
+v 0 0 0
+v 1 0 0
+v 0 1 0
+v 0 0 0
+v 0 1 0
+v 0 0 1
+f 1 2 3
+f 4 5 6
+";
+        let (vertices, _) = build_vertices(&parse_obj(obj), 180.0);
+        for position in [[0.0, 0.0, 0.0], [0.0, 1.0, 0.0]] {
+            let normals = normals_at(&vertices, position);
+            assert_eq!(normals.len(), 1, "{:?} was not welded", position);
+            let half = std::f32::consts::FRAC_1_SQRT_2;
+            assert_close(normals[0], [half, 0.0, half]);
+        }
+    }
+
+    #[test]
+    fn vertex_colors_are_kept() {
+        let obj = "\
+v 0 0 0 1 0 0
+v 1 0 0 0 1 0
+v 0 1 0 0 0 1
+f 1 2 3
+";
+        let (vertices, _) = build_vertices(&parse_obj(obj), SMOOTHING_ANGLE);
+        let color_at = |position| {
+            vertices
+                .iter()
+                .find(|v| v.position == position)
+                .unwrap()
+                .color
+        };
+        assert_eq!(color_at([0.0, 0.0, 0.0]), [1.0, 0.0, 0.0]);
+        assert_eq!(color_at([1.0, 0.0, 0.0]), [0.0, 1.0, 0.0]);
+        assert_eq!(color_at([0.0, 1.0, 0.0]), [0.0, 0.0, 1.0]);
+    }
+
+    fn parse_mtl(mtl: &str) -> Vec<tobj::Material> {
+        let mtl = default_diffuse(mtl);
+        let (materials, _) = tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mtl))).unwrap();
+        materials
+    }
+
+    #[test]
+    fn missing_kd_defaults_to_white() {
+        let materials = parse_mtl("newmtl plain\nNs 250\n\nnewmtl red\nKd 1 0 0\n");
+        let plain = MaterialUniform::from_obj(&materials[0]);
+        let red = MaterialUniform::from_obj(&materials[1]);
+        assert_eq!(plain.base_color, [1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(red.base_color, [1.0, 0.0, 0.0, 1.0]);
+    }
 }
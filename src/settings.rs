@@ -0,0 +1,334 @@
+use std::path::PathBuf;
+
+use crate::{
+    adapter::AdapterConfig,
+    assets::{AssetSource, Assets},
+    clock::FixedTimestep,
+    ibl,
+    light::{LightKind, MAX_SHADOWED_LIGHTS},
+    shadow::{ShadowConfig, MAX_CASCADES},
+    skybox::SkyboxSource,
+};
+
+/// Start-up settings, read from the environment and then overridden by
+/// command line flags.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub adapter: AdapterConfig,
+    /// Requested MSAA sample count, lowered to what the adapter supports.
+    pub sample_count: u32,
+    /// Requested present mode, resolved against what the surface supports.
+    pub present_mode: wgpu::PresentMode,
+    /// Run the simulation at this many steps per second and interpolate
+    /// between steps, instead of once per rendered frame.
+    pub fixed_update_rate: Option<f64>,
+    /// Where models and textures are read from.
+    pub assets: Assets,
+    /// Model drawn for every instance, as an asset path. glTF for `.gltf`
+    /// and `.glb` files, OBJ otherwise.
+    pub model: String,
+    /// Anisotropic filtering samples for model textures, 1 for none.
+    pub anisotropy: u8,
+    /// Kind of the scene's main light.
+    pub light: LightKind,
+    /// Extra shadow casting point lights, up to [`MAX_SHADOWED_LIGHTS`].
+    pub point_lights: usize,
+    pub shadow: ShadowConfig,
+    /// Cube map drawn behind the scene. Without one the frame is cleared to
+    /// `clear_color`.
+    pub skybox: Option<SkyboxSource>,
+    /// Where image based lighting computed from the skybox is cached, if
+    /// anywhere.
+    pub ibl_cache: Option<PathBuf>,
+    pub clear_color: wgpu::Color,
+    /// Render a single frame to this file instead of opening a window.
+    pub headless_output: Option<String>,
+    pub headless_size: (u32, u32),
+    /// Print the available adapters and exit.
+    pub list_adapters: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            adapter: AdapterConfig::default(),
+            sample_count: 4,
+            present_mode: wgpu::PresentMode::AutoVsync,
+            fixed_update_rate: None,
+            assets: Assets::default(),
+            model: String::from("cube.obj"),
+            anisotropy: 1,
+            light: LightKind::Directional,
+            point_lights: 0,
+            shadow: ShadowConfig::default(),
+            skybox: None,
+            ibl_cache: Some(ibl::default_cache_dir()),
+            clear_color: wgpu::Color {
+                r: 0.2,
+                g: 0.3,
+                b: 0.4,
+                a: 1.0,
+            },
+            headless_output: None,
+            headless_size: (800, 600),
+            list_adapters: false,
+        }
+    }
+}
+
+impl Settings {
+    pub const USAGE: &'static str = "\
+usage: hello_wgpu [options]
+
+  --backend <list>       comma separated backends (vulkan, dx12, metal, gl)
+  --power <low|high>     adapter power preference
+  --adapter <name>       pick the adapter whose name contains <name>
+  --fallback             only use a software adapter
+  --no-fallback          never fall back to a software adapter
+  --msaa <samples>       MSAA sample count (1, 2, 4 or 8; M cycles at runtime)
+  --present-mode <mode>  vsync, no-vsync, fifo, fifo-relaxed, mailbox or immediate
+                         (V toggles vsync at runtime)
+  --fixed-timestep <hz>  simulate at a fixed rate and interpolate between steps
+  --assets <dir>         search <dir> for assets first (repeatable; also
+                         HELLO_WGPU_ASSETS, then res/ and built-in assets)
+  --model <file>         OBJ, glTF or GLB model asset (default cube.obj)
+  --anisotropy <n>       anisotropic texture filtering, 1, 2, 4, 8 or 16
+                         (default 1, off)
+  --light <kind>         point, directional or spot (default directional)
+  --point-lights <n>     extra shadow casting point lights, 0 to 4
+  --shadow-size <px>     shadow map resolution (default 2048)
+  --point-shadow-size <px>
+                         point light shadow cube resolution (default 512)
+  --cascades <n>         directional shadow cascades, 1 to 4 (default 4;
+                         C shows them at runtime)
+  --shadow-bias <bias>   constant shadow depth bias (default 2)
+  --shadow-slope <bias>  slope scaled shadow depth bias (default 2.0)
+  --skybox <files>       equirectangular panorama, or six comma separated
+                         cube faces in +X,-X,+Y,-Y,+Z,-Z order
+  --ibl-cache <dir|off>  cache lighting computed from the skybox in <dir>
+                         (default ~/.cache/hello_wgpu)
+  --clear-color <r,g,b>  background without a skybox (default 0.2,0.3,0.4)
+  --list-adapters        print the available adapters and exit
+  --headless [file]      render one frame to a PNG (default frame.png)
+  --size <w>x<h>         size of the headless frame";
+
+    pub fn from_env_and_args() -> anyhow::Result<Self> {
+        let mut settings = Self {
+            adapter: AdapterConfig::from_env(),
+            ..Default::default()
+        };
+        settings.apply_args(std::env::args().skip(1))?;
+        Ok(settings)
+    }
+
+    pub fn apply_args(&mut self, args: impl IntoIterator<Item = String>) -> anyhow::Result<()> {
+        let mut args = args.into_iter().peekable();
+
+        while let Some(arg) = args.next() {
+            let mut value = |flag: &str| {
+                args.next()
+                    .ok_or_else(|| anyhow::anyhow!("{} expects a value\n\n{}", flag, Self::USAGE))
+            };
+
+            match arg.as_str() {
+                "--backend" => {
+                    self.adapter.backends =
+                        wgpu::util::parse_backends_from_comma_list(&value("--backend")?);
+                }
+                "--power" => {
+                    self.adapter.power_preference = match value("--power")?.as_str() {
+                        "low" => wgpu::PowerPreference::LowPower,
+                        "high" => wgpu::PowerPreference::HighPerformance,
+                        other => anyhow::bail!("unknown power preference {:?}", other),
+                    };
+                }
+                "--adapter" => self.adapter.name = Some(value("--adapter")?),
+                "--fallback" => self.adapter.force_fallback = true,
+                "--no-fallback" => self.adapter.allow_fallback = false,
+                "--msaa" => {
+                    let samples = value("--msaa")?;
+                    self.sample_count = samples
+                        .parse()
+                        .map_err(|_| anyhow::anyhow!("invalid sample count {:?}", samples))?;
+                }
+                "--present-mode" => {
+                    self.present_mode = match value("--present-mode")?.as_str() {
+                        "vsync" => wgpu::PresentMode::AutoVsync,
+                        "no-vsync" => wgpu::PresentMode::AutoNoVsync,
+                        "fifo" => wgpu::PresentMode::Fifo,
+                        "fifo-relaxed" => wgpu::PresentMode::FifoRelaxed,
+                        "mailbox" => wgpu::PresentMode::Mailbox,
+                        "immediate" => wgpu::PresentMode::Immediate,
+                        other => anyhow::bail!("unknown present mode {:?}", other),
+                    };
+                }
+                "--fixed-timestep" => {
+                    let rate = value("--fixed-timestep")?;
+                    self.fixed_update_rate = Some(
+                        rate.parse()
+                            .ok()
+                            .filter(|&hz| FixedTimestep::from_rate(hz).is_some())
+                            .ok_or_else(|| anyhow::anyhow!("invalid update rate {:?}", rate))?,
+                    );
+                }
+                "--assets" => {
+                    let dir = value("--assets")?;
+                    self.assets.push_front(AssetSource::Directory(dir.into()));
+                }
+                "--model" => self.model = value("--model")?,
+                "--anisotropy" => {
+                    let samples = value("--anisotropy")?;
+                    self.anisotropy = samples
+                        .parse()
+                        .ok()
+                        .filter(|&n: &u8| n.is_power_of_two() && n <= 16)
+                        .ok_or_else(|| anyhow::anyhow!("invalid anisotropy {:?}", samples))?;
+                }
+                "--light" => {
+                    self.light = match value("--light")?.as_str() {
+                        "point" => LightKind::Point,
+                        "directional" => LightKind::Directional,
+                        "spot" => LightKind::Spot,
+                        other => anyhow::bail!("unknown light kind {:?}", other),
+                    };
+                }
+                "--point-lights" => {
+                    let count = value("--point-lights")?;
+                    self.point_lights = count
+                        .parse()
+                        .ok()
+                        .filter(|&n| n <= MAX_SHADOWED_LIGHTS)
+                        .ok_or_else(|| anyhow::anyhow!("invalid point light count {:?}", count))?;
+                }
+                "--shadow-size" => {
+                    let size = value("--shadow-size")?;
+                    self.shadow.size = size
+                        .parse()
+                        .ok()
+                        .filter(|&size: &u32| size > 0)
+                        .ok_or_else(|| anyhow::anyhow!("invalid shadow map size {:?}", size))?;
+                }
+                "--point-shadow-size" => {
+                    let size = value("--point-shadow-size")?;
+                    self.shadow.point_size = size
+                        .parse()
+                        .ok()
+                        .filter(|&size: &u32| size > 0)
+                        .ok_or_else(|| anyhow::anyhow!("invalid shadow map size {:?}", size))?;
+                }
+                "--cascades" => {
+                    let cascades = value("--cascades")?;
+                    self.shadow.cascades = cascades
+                        .parse()
+                        .ok()
+                        .filter(|n| (1..=MAX_CASCADES as u32).contains(n))
+                        .ok_or_else(|| anyhow::anyhow!("invalid cascade count {:?}", cascades))?;
+                }
+                "--shadow-bias" => {
+                    let bias = value("--shadow-bias")?;
+                    self.shadow.constant_bias = bias
+                        .parse()
+                        .map_err(|_| anyhow::anyhow!("invalid shadow bias {:?}", bias))?;
+                }
+                "--shadow-slope" => {
+                    let bias = value("--shadow-slope")?;
+                    self.shadow.slope_bias = bias
+                        .parse()
+                        .map_err(|_| anyhow::anyhow!("invalid shadow slope bias {:?}", bias))?;
+                }
+                "--skybox" => {
+                    let files = value("--skybox")?;
+                    let files = files.split(',').map(String::from).collect::<Vec<_>>();
+                    self.skybox = Some(match files.len() {
+                        1 => SkyboxSource::Equirect(files[0].clone()),
+                        _ => SkyboxSource::Faces(files.try_into().map_err(|files: Vec<_>| {
+                            anyhow::anyhow!("a skybox needs 1 or 6 images, not {}", files.len())
+                        })?),
+                    });
+                }
+                "--ibl-cache" => {
+                    self.ibl_cache = match value("--ibl-cache")?.as_str() {
+                        "off" => None,
+                        dir => Some(dir.into()),
+                    };
+                }
+                "--clear-color" => {
+                    let color = value("--clear-color")?;
+                    let channels = color
+                        .split(',')
+                        .map(|channel| channel.trim().parse::<f64>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .ok()
+                        .filter(|channels| channels.len() == 3)
+                        .ok_or_else(|| anyhow::anyhow!("invalid clear color {:?}", color))?;
+                    self.clear_color = wgpu::Color {
+                        r: channels[0],
+                        g: channels[1],
+                        b: channels[2],
+                        a: 1.0,
+                    };
+                }
+                "--list-adapters" => self.list_adapters = true,
+                "--headless" => {
+                    let output = match args.peek() {
+                        Some(next) if !next.starts_with("--") => args.next().unwrap(),
+                        _ => String::from("frame.png"),
+                    };
+                    self.headless_output = Some(output);
+                }
+                "--size" => {
+                    let size = value("--size")?;
+                    let (width, height) = size
+                        .split_once('x')
+                        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                        .ok_or_else(|| anyhow::anyhow!("invalid size {:?}", size))?;
+                    if width == 0 || height == 0 {
+                        anyhow::bail!("size {:?} has no pixels", size);
+                    }
+                    self.headless_size = (width, height);
+                }
+                "--help" | "-h" => anyhow::bail!("{}", Self::USAGE),
+                other => anyhow::bail!("unknown argument {:?}\n\n{}", other, Self::USAGE),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(args: &[&str]) -> anyhow::Result<Settings> {
+        let mut settings = Settings::default();
+        settings.apply_args(args.iter().map(|arg| arg.to_string()))?;
+        Ok(settings)
+    }
+
+    #[test]
+    fn unusable_update_rates_are_rejected() {
+        let settings = apply(&["--fixed-timestep", "120"]).unwrap();
+        assert_eq!(settings.fixed_update_rate, Some(120.0));
+        for rate in ["0", "-30", "inf", "NaN", "1e300", "1e-300", "fast"] {
+            assert!(
+                apply(&["--fixed-timestep", rate]).is_err(),
+                "accepted {:?}",
+                rate
+            );
+        }
+    }
+
+    #[test]
+    fn size_is_parsed() {
+        let settings = apply(&["--size", "640x480"]).unwrap();
+        assert_eq!(settings.headless_size, (640, 480));
+    }
+
+    #[test]
+    fn empty_sizes_are_rejected() {
+        for size in ["0x0", "0x600", "800x0", "800", "x600", "-1x600"] {
+            assert!(apply(&["--size", size]).is_err(), "accepted {:?}", size);
+        }
+    }
+}
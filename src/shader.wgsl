@@ -3,26 +3,121 @@ struct InstanceInput{
     @location(6) model_matrix_1: vec4<f32>,
     @location(7) model_matrix_2: vec4<f32>,
     @location(8) model_matrix_3: vec4<f32>,
+    @location(9) normal_matrix_0: vec3<f32>,
+    @location(10) normal_matrix_1: vec3<f32>,
+    @location(11) normal_matrix_2: vec3<f32>,
 }
 
 
 
 struct CameraUniform {
-    view_proj: mat4x4<f32>
-
+    view_position: vec4<f32>,
+    view_proj: mat4x4<f32>,
+    view: mat4x4<f32>,
 };
 
 @group(1) @binding(0)
 var<uniform> camera: CameraUniform;
 
+struct Light {
+    position: vec3<f32>,
+    ambient_strength: f32,
+    color: vec3<f32>,
+    kind: u32,
+    direction: vec3<f32>,
+    inner_cutoff: f32,
+    outer_cutoff: f32,
+};
+
+const LIGHT_POINT: u32 = 0u;
+const LIGHT_DIRECTIONAL: u32 = 1u;
+const LIGHT_SPOT: u32 = 2u;
+
+@group(2) @binding(0)
+var<uniform> light: Light;
+
+// Lights besides the main one, with the shadow casting point lights first.
+struct ListLight {
+    position: vec3<f32>,
+    range: f32,
+    color: vec3<f32>,
+    kind: u32,
+    direction: vec3<f32>,
+    inner_cutoff: f32,
+    outer_cutoff: f32,
+};
+
+struct LightCount {
+    count: u32,
+    shadowed: u32,
+};
+
+@group(2) @binding(1)
+var<uniform> light_count: LightCount;
+@group(2) @binding(2)
+var<storage, read> lights: array<ListLight>;
+
+struct Environment {
+    intensity: f32,
+    specular_levels: f32,
+};
+
+// Image based lighting: irradiance for diffuse light, the environment
+// blurred per roughness for specular light, and the split sum BRDF table.
+@group(2) @binding(3)
+var t_irradiance: texture_cube<f32>;
+@group(2) @binding(4)
+var t_prefiltered: texture_cube<f32>;
+@group(2) @binding(5)
+var t_brdf_lut: texture_2d<f32>;
+@group(2) @binding(6)
+var s_environment: sampler;
+@group(2) @binding(7)
+var<uniform> environment: Environment;
+
+struct Cascades {
+    view_proj: array<mat4x4<f32>, 4>,
+    splits: vec4<f32>,
+    count: u32,
+    debug: u32,
+    blend: f32,
+};
+
+@group(3) @binding(0)
+var t_shadow: texture_depth_2d_array;
+@group(3) @binding(1)
+var s_shadow: sampler_comparison;
+@group(3) @binding(2)
+var<uniform> cascades: Cascades;
+
+struct PointShadows {
+    lights: array<vec4<f32>, 5>,
+    count: u32,
+    first_point_light: u32,
+};
+
+@group(3) @binding(3)
+var t_point_shadow: texture_depth_cube_array;
+@group(3) @binding(4)
+var<uniform> point_shadows: PointShadows;
+
 struct VertexInput{
     @location(0) position: vec3<f32>,
-    @location(1) tex_coords: vec2<f32>
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) tangent: vec3<f32>,
+    @location(4) bitangent: vec3<f32>,
+    @location(12) color: vec3<f32>,
 }
 
 struct VertexOutput{
     @builtin(position) clip_position: vec4<f32>,
-    @location(0) tex_coords: vec2<f32>
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) world_position: vec3<f32>,
+    @location(3) world_tangent: vec3<f32>,
+    @location(4) world_bitangent: vec3<f32>,
+    @location(5) color: vec3<f32>,
 }
 
 @vertex
@@ -36,20 +131,318 @@ fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput{
         instance.model_matrix_3,
     );
 
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
+
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
-    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+    out.world_normal = normal_matrix * model.normal;
+    out.world_tangent = normal_matrix * model.tangent;
+    out.world_bitangent = normal_matrix * model.bitangent;
+    out.world_position = world_position.xyz;
+    out.color = model.color;
+    out.clip_position = camera.view_proj * world_position;
     return out;
 
 }
 
 @group(0) @binding(0)
-var t_diffuse: texture_2d<f32>;
+var t_base_color: texture_2d<f32>;
 @group(0) @binding(1)
-var s_diffuse: sampler;
+var s_base_color: sampler;
+@group(0) @binding(2)
+var t_normal: texture_2d<f32>;
+@group(0) @binding(3)
+var s_normal: sampler;
+@group(0) @binding(4)
+var t_roughness: texture_2d<f32>;
+@group(0) @binding(5)
+var s_roughness: sampler;
+@group(0) @binding(6)
+var t_metallic: texture_2d<f32>;
+@group(0) @binding(7)
+var s_metallic: sampler;
+@group(0) @binding(8)
+var t_occlusion: texture_2d<f32>;
+@group(0) @binding(9)
+var s_occlusion: sampler;
+@group(0) @binding(10)
+var t_emissive: texture_2d<f32>;
+@group(0) @binding(11)
+var s_emissive: sampler;
+
+struct Material {
+    base_color: vec4<f32>,
+    emissive: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    occlusion_strength: f32,
+    normal_scale: f32,
+    illumination_model: u32,
+};
+
+@group(0) @binding(12)
+var<uniform> material: Material;
+
+const PI: f32 = 3.14159265359;
+
+// What the lighting needs to know about the point being shaded.
+struct Surface {
+    normal: vec3<f32>,
+    view_dir: vec3<f32>,
+    // Base color left for diffuse reflection, none for metals.
+    diffuse_color: vec3<f32>,
+    // Reflectance at normal incidence: 4% for dielectrics, the base color
+    // for metals.
+    f0: vec3<f32>,
+    roughness: f32,
+};
+
+// Fraction of light reaching `world_position` in one cascade, averaged over a
+// 3x3 block of shadow map texels.
+fn sample_cascade(index: u32, world_position: vec3<f32>) -> f32 {
+    let clip = cascades.view_proj[index] * vec4<f32>(world_position, 1.0);
+    if clip.w <= 0.0 {
+        return 1.0;
+    }
+
+    let ndc = clip.xyz / clip.w;
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_shadow));
+    var lit = 0.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel;
+            lit += textureSampleCompareLevel(t_shadow, s_shadow, uv + offset, i32(index), ndc.z);
+        }
+    }
+    return lit / 9.0;
+}
+
+// First cascade whose far split lies beyond `view_depth`.
+fn cascade_index(view_depth: f32) -> u32 {
+    var index = 0u;
+    while index + 1u < cascades.count && view_depth > cascades.splits[index] {
+        index++;
+    }
+    return index;
+}
+
+// Fraction of light reaching `world_position`, fading into the next cascade
+// near the far end of each one to hide the seam.
+fn shadow_factor(world_position: vec3<f32>, view_depth: f32) -> f32 {
+    if cascades.count == 0u {
+        return 1.0;
+    }
+
+    let index = cascade_index(view_depth);
+    var lit = sample_cascade(index, world_position);
+
+    if index + 1u < cascades.count {
+        var start = 0.0;
+        if index > 0u {
+            start = cascades.splits[index - 1u];
+        }
+        let end = cascades.splits[index];
+        let fade_start = end - (end - start) * cascades.blend;
+        if view_depth > fade_start {
+            let t = (view_depth - fade_start) / (end - fade_start);
+            lit = mix(lit, sample_cascade(index + 1u, world_position), t);
+        }
+    }
+
+    return lit;
+}
+
+// Fraction of light from the point light with cube map `cube` reaching
+// `world_position`. The position is pushed out along the surface normal to
+// keep surfaces from shadowing themselves.
+//
+// GLSL has no explicit level comparison sample for cube arrays, so this uses
+// implicit derivatives and must stay in uniform control flow.
+fn point_shadow_factor(cube: u32, world_position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    let light = point_shadows.lights[cube];
+    let to_fragment = world_position + normal * 0.05 - light.xyz;
+    let depth = length(to_fragment) / light.w - 0.002;
+    let lit = textureSampleCompare(t_point_shadow, s_shadow, to_fragment, i32(cube), depth);
+    return select(lit, 1.0, cube >= point_shadows.count);
+}
+
+// Trowbridge-Reitz (GGX) distribution of microfacet normals.
+fn ggx_distribution(n_dot_h: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let denominator = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (PI * denominator * denominator);
+}
+
+// Smith's shadowing and masking with Schlick's approximation, remapped for
+// analytic lights.
+fn smith_geometry(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return g_v * g_l;
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// Cook-Torrance light reflected towards the viewer from one light, before
+// attenuation. Light colors are scaled by pi, so a white light lights a
+// white surface facing it to full brightness.
+fn shade(light_color: vec3<f32>, light_dir: vec3<f32>, surface: Surface) -> vec3<f32> {
+    let n_dot_l = max(dot(surface.normal, light_dir), 0.0);
+    let n_dot_v = max(dot(surface.normal, surface.view_dir), 1e-4);
+    let half_dir = normalize(surface.view_dir + light_dir);
+    let fresnel = fresnel_schlick(max(dot(surface.view_dir, half_dir), 0.0), surface.f0);
+
+    var specular = vec3<f32>(0.0);
+    var diffuse = surface.diffuse_color / PI;
+    if material.illumination_model != 1u {
+        let alpha = surface.roughness * surface.roughness;
+        let distribution = ggx_distribution(max(dot(surface.normal, half_dir), 0.0), alpha);
+        let geometry = smith_geometry(n_dot_v, n_dot_l, surface.roughness);
+        specular = distribution * geometry * fresnel / max(4.0 * n_dot_v * n_dot_l, 1e-4);
+        diffuse *= 1.0 - fresnel;
+    }
+
+    return light_color * PI * (diffuse + specular) * n_dot_l;
+}
+
+// Light from a light in the list, fading out towards its range and, for
+// spot lights, towards the edge of its cone.
+fn list_light(listed: ListLight, world_position: vec3<f32>, surface: Surface) -> vec3<f32> {
+    var light_dir = -listed.direction;
+    var falloff = 1.0;
+    if listed.kind != LIGHT_DIRECTIONAL {
+        let to_light = listed.position - world_position;
+        let distance = length(to_light);
+        light_dir = to_light / distance;
+        falloff = pow(clamp(1.0 - distance / listed.range, 0.0, 1.0), 2.0);
+    }
+    if listed.kind == LIGHT_SPOT {
+        let theta = dot(-light_dir, listed.direction);
+        falloff *= smoothstep(listed.outer_cutoff, listed.inner_cutoff, theta);
+    }
+    return shade(listed.color, light_dir, surface) * falloff;
+}
+
+// Light from the surroundings, dimmed by ambient occlusion. Without an
+// environment map this is a flat fraction of the main light's color.
+fn ambient_light(surface: Surface, base_color: vec3<f32>, occlusion: f32) -> vec3<f32> {
+    if environment.intensity <= 0.0 {
+        return light.color * light.ambient_strength * base_color * occlusion;
+    }
+
+    let irradiance = textureSampleLevel(t_irradiance, s_environment, surface.normal, 0.0).rgb;
+    var diffuse = irradiance * surface.diffuse_color;
+    var specular = vec3<f32>(0.0);
+
+    if material.illumination_model != 1u {
+        let n_dot_v = max(dot(surface.normal, surface.view_dir), 0.0);
+        // Rough surfaces reflect less at grazing angles than Schlick's
+        // approximation predicts for a mirror.
+        let f_max = max(vec3<f32>(1.0 - surface.roughness), surface.f0);
+        let fresnel = surface.f0 + (f_max - surface.f0) * pow(1.0 - n_dot_v, 5.0);
+        diffuse *= 1.0 - fresnel;
+
+        let reflected = reflect(-surface.view_dir, surface.normal);
+        let lod = surface.roughness * (environment.specular_levels - 1.0);
+        let prefiltered = textureSampleLevel(t_prefiltered, s_environment, reflected, lod).rgb;
+        let brdf = textureSampleLevel(t_brdf_lut, s_environment, vec2<f32>(n_dot_v, surface.roughness), 0.0).rg;
+        specular = prefiltered * (surface.f0 * brdf.x + brdf.y);
+    }
+
+    return (diffuse + specular) * occlusion * environment.intensity;
+}
+
+fn cascade_color(index: u32) -> vec3<f32> {
+    var colors = array<vec3<f32>, 4>(
+        vec3<f32>(1.0, 0.2, 0.2),
+        vec3<f32>(0.2, 1.0, 0.2),
+        vec3<f32>(0.2, 0.2, 1.0),
+        vec3<f32>(1.0, 1.0, 0.2),
+    );
+    return colors[index];
+}
 
 @fragment
 
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32>{
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
-}
\ No newline at end of file
+    let texture_color = textureSample(t_base_color, s_base_color, in.tex_coords);
+    let base_color = texture_color.rgb * material.base_color.rgb * in.color;
+    let alpha = texture_color.a * material.base_color.a;
+
+    let roughness = textureSample(t_roughness, s_roughness, in.tex_coords).g * material.roughness;
+    let metallic = textureSample(t_metallic, s_metallic, in.tex_coords).b * material.metallic;
+    let occlusion = mix(1.0, textureSample(t_occlusion, s_occlusion, in.tex_coords).r, material.occlusion_strength);
+    let emissive = textureSample(t_emissive, s_emissive, in.tex_coords).rgb * material.emissive;
+
+    let object_normal = textureSample(t_normal, s_normal, in.tex_coords).xyz * 2.0 - 1.0;
+
+    let tangent_matrix = mat3x3<f32>(
+        normalize(in.world_tangent),
+        normalize(in.world_bitangent),
+        normalize(in.world_normal),
+    );
+    let normal = normalize(tangent_matrix * (object_normal * vec3<f32>(material.normal_scale, material.normal_scale, 1.0)));
+
+    let surface_normal = normalize(in.world_normal);
+    let view_dir = normalize(camera.view_position.xyz - in.world_position);
+
+    var surface: Surface;
+    surface.normal = normal;
+    surface.view_dir = view_dir;
+    surface.diffuse_color = base_color * (1.0 - metallic);
+    surface.f0 = mix(vec3<f32>(0.04), base_color, metallic);
+    // Perfectly smooth surfaces would reflect lights as infinitely small points.
+    surface.roughness = clamp(roughness, 0.03, 1.0);
+
+    var light_dir = normalize(light.position - in.world_position);
+    if light.kind == LIGHT_DIRECTIONAL {
+        light_dir = -light.direction;
+    }
+
+    let view_depth = -(camera.view * vec4<f32>(in.world_position, 1.0)).z;
+    var attenuation = shadow_factor(in.world_position, view_depth);
+    if light.kind == LIGHT_POINT {
+        attenuation = point_shadow_factor(0u, in.world_position, surface_normal);
+    }
+    if light.kind == LIGHT_SPOT {
+        let theta = dot(-light_dir, light.direction);
+        attenuation *= smoothstep(light.outer_cutoff, light.inner_cutoff, theta);
+    }
+
+    var result = ambient_light(surface, base_color, occlusion) + emissive;
+    result += shade(light.color, light_dir, surface) * attenuation;
+
+    // Sampling the shadow cubes has to stay in uniform control flow, so the
+    // lights with one get a loop of their own.
+    for (var i = 0u; i < light_count.shadowed; i++) {
+        let shadow = point_shadow_factor(point_shadows.first_point_light + i, in.world_position, surface_normal);
+        result += list_light(lights[i], in.world_position, surface) * shadow;
+    }
+    for (var i = light_count.shadowed; i < light_count.count; i++) {
+        result += list_light(lights[i], in.world_position, surface);
+    }
+
+    if material.illumination_model == 0u {
+        result = base_color + emissive;
+    }
+
+    if cascades.debug != 0u && cascades.count > 0u {
+        result = mix(result, cascade_color(cascade_index(view_depth)), 0.4);
+    }
+
+    return vec4<f32>(result, alpha);
+}
@@ -0,0 +1,880 @@
+use cgmath::{EuclideanSpace, InnerSpace, SquareMatrix, Transform};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    camera::Camera,
+    instance::{InstanceRaw, Instances},
+    light::{LightInit, LightKind, LightUniform, MAX_SHADOWED_LIGHTS},
+    model::{self, DrawGeometry, Model, Vertex},
+    texture,
+};
+
+/// Most cascades a directional light can split the view into. Matches the
+/// array sizes in `Cascades` in the shader.
+pub const MAX_CASCADES: usize = 4;
+
+/// Most point light cube maps: the main light when it is a point light, and
+/// every shadow casting light in the light list. Matches the array size in
+/// `PointShadows` in the shader.
+pub const MAX_POINT_SHADOWS: usize = MAX_SHADOWED_LIGHTS + 1;
+
+/// Shadow map resolution, cascade count and the depth bias applied while
+/// rendering the maps.
+#[derive(Debug, Clone, Copy)]
+pub struct ShadowConfig {
+    /// Width and height of each shadow map in texels.
+    pub size: u32,
+    /// Width and height of each point light cube map face in texels.
+    pub point_size: u32,
+    /// Number of cascades a directional light uses, up to [`MAX_CASCADES`].
+    pub cascades: u32,
+    /// Constant depth bias, in units of the smallest depth difference.
+    pub constant_bias: i32,
+    /// Depth bias scaled by the slope of each triangle, which keeps surfaces
+    /// facing away from the light from shadowing themselves.
+    pub slope_bias: f32,
+}
+
+impl Default for ShadowConfig {
+    fn default() -> Self {
+        Self {
+            size: 2048,
+            point_size: 512,
+            cascades: 4,
+            constant_bias: 2,
+            slope_bias: 2.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CascadeUniform {
+    /// World to clip space for each cascade's shadow map layer.
+    view_proj: [[[f32; 4]; 4]; MAX_CASCADES],
+    /// View space depth at which each cascade ends.
+    splits: [f32; MAX_CASCADES],
+    /// Cascades in use; zero when the main light is a point light.
+    count: u32,
+    /// Non-zero tints each cascade a different color.
+    pub debug: u32,
+    /// Fraction of each cascade, at its far end, faded into the next one.
+    pub blend: f32,
+    _padding: f32,
+}
+
+impl Default for CascadeUniform {
+    fn default() -> Self {
+        Self {
+            view_proj: [cgmath::Matrix4::identity().into(); MAX_CASCADES],
+            splits: [0.0; MAX_CASCADES],
+            count: 0,
+            debug: 0,
+            blend: 0.1,
+            _padding: 0.0,
+        }
+    }
+}
+
+/// Positions and ranges of the point lights with cube shadow maps.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PointShadowUniform {
+    /// Light position in `xyz` and range in `w`, one per cube map.
+    lights: [[f32; 4]; MAX_POINT_SHADOWS],
+    /// Cube maps in use.
+    count: u32,
+    /// Cube map of the first light in the light list; the main light uses
+    /// cube 0 when it is a point light.
+    first_point_light: u32,
+    _padding: [u32; 2],
+}
+
+/// Uniform bound while rendering one shadow map layer or cube face.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowPassUniform {
+    view_proj: [[f32; 4]; 4],
+    light_position: [f32; 3],
+    range: f32,
+}
+
+/// Depth textures rendered from the lights' points of view, and the bind group
+/// the main pass samples them through: one array layer per cascade of the
+/// main light, and one cube map per shadow casting point light.
+pub struct ShadowMap {
+    pub config: ShadowConfig,
+    pub texture: texture::Texture,
+    pub cube_texture: texture::CubeTexture,
+    pub uniform: CascadeUniform,
+    buffer: wgpu::Buffer,
+    pub point_uniform: PointShadowUniform,
+    point_buffer: wgpu::Buffer,
+    layers: Vec<ShadowLayer>,
+    /// Six faces per cube, in cube map face order.
+    faces: Vec<ShadowLayer>,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    point_pipeline: wgpu::RenderPipeline,
+    /// Whether the cube maps are a cube array, rather than a single cube.
+    cube_arrays: bool,
+}
+
+/// One layer of the shadow map, with the projection used to render it.
+struct ShadowLayer {
+    view: wgpu::TextureView,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl ShadowLayer {
+    fn new(device: &wgpu::Device, view: wgpu::TextureView, layout: &wgpu::BindGroupLayout) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("shadow layer buffer"),
+            size: std::mem::size_of::<ShadowPassUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("shadow layer bind group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        Self {
+            view,
+            buffer,
+            bind_group,
+        }
+    }
+
+    fn write(&self, queue: &wgpu::Queue, uniform: ShadowPassUniform) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+}
+
+impl ShadowMap {
+    /// Blend between logarithmic (1.0) and uniform (0.0) cascade splits.
+    const SPLIT_LAMBDA: f32 = 0.75;
+    /// How far behind each cascade the light's projection starts, so objects
+    /// outside the view can still cast shadows into it.
+    const CASTER_DISTANCE: f32 = 50.0;
+    /// The main light has no falloff, so as a point light its cube map covers
+    /// the whole scene.
+    const MAIN_POINT_LIGHT_RANGE: f32 = 50.0;
+
+    /// How the shaders declare the point shadow cube maps, and sample them.
+    /// [`ShadowMap::shader_source`] replaces both for a single cube.
+    const CUBE_ARRAY_DECLARATION: &'static str = "var t_point_shadow: texture_depth_cube_array;";
+    const CUBE_ARRAY_SAMPLE: &'static str =
+        "textureSampleCompare(t_point_shadow, s_shadow, to_fragment, i32(cube), depth)";
+
+    /// Creates the shadow maps for `light`, with a cube map for each point
+    /// light in its list that can cast shadows. Without cube array support
+    /// there is a single cube, see [`max_point_shadows`].
+    pub fn new(
+        device: &wgpu::Device,
+        adapter: &wgpu::Adapter,
+        config: ShadowConfig,
+        light: &LightInit,
+    ) -> Self {
+        let config = ShadowConfig {
+            cascades: config.cascades.clamp(1, MAX_CASCADES as u32),
+            ..config
+        };
+
+        // The GL backend treats single layer textures as plain 2D textures,
+        // which cannot be viewed as an array, so always allocate two.
+        let layer_count = config.cascades.max(2);
+        let texture =
+            texture::Texture::create_shadow_map(device, config.size, layer_count, "shadow map");
+
+        let first_point_light = u32::from(light.kind == LightKind::Point);
+        let cube_count = first_point_light + light.lights.shadow_cubes() as u32;
+        // Likewise a single cube becomes a plain cube map on GL rather than a
+        // cube array, and the bind group needs a cube even when none is used.
+        let cube_arrays = supports_cube_arrays(adapter);
+        let cube_texture = texture::CubeTexture::create_depth_cube_array(
+            device,
+            config.point_size,
+            if cube_arrays { cube_count.max(2) } else { 1 },
+            "point shadow maps",
+        );
+
+        let uniform = CascadeUniform::default();
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("cascade buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let point_uniform = PointShadowUniform {
+            lights: [[0.0; 4]; MAX_POINT_SHADOWS],
+            count: cube_count,
+            first_point_light,
+            _padding: [0; 2],
+        };
+        let point_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("point shadow buffer"),
+            contents: bytemuck::cast_slice(&[point_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let layer_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("shadow layer bind group layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+
+        let layers = (0..config.cascades)
+            .map(|layer| {
+                let view = texture.texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("shadow layer view"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: std::num::NonZeroU32::new(1),
+                    ..Default::default()
+                });
+                ShadowLayer::new(device, view, &layer_bind_group_layout)
+            })
+            .collect();
+
+        let faces = (0..cube_count)
+            .flat_map(|cube| (0..6).map(move |face| (cube, face)))
+            .map(|(cube, face)| {
+                ShadowLayer::new(
+                    device,
+                    cube_texture.face_view(cube, face),
+                    &layer_bind_group_layout,
+                )
+            })
+            .collect();
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("shadow bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: if cube_arrays {
+                            wgpu::TextureViewDimension::CubeArray
+                        } else {
+                            wgpu::TextureViewDimension::Cube
+                        },
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("shadow bind group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&cube_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: point_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow pipeline layout"),
+            bind_group_layouts: &[&layer_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shadow shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shadow.wgsl").into()),
+        });
+
+        let pipeline = create_shadow_pipeline(
+            device,
+            &layout,
+            &shader,
+            None,
+            Some(wgpu::Face::Back),
+            wgpu::DepthBiasState {
+                constant: config.constant_bias,
+                slope_scale: config.slope_bias,
+                clamp: 0.0,
+            },
+        );
+
+        // Cube faces are rendered mirrored, see `cube_face_view_proj`, which
+        // flips the winding of every triangle. Point shadows write their own
+        // depth, so the depth bias would not apply to them either.
+        let point_pipeline = create_shadow_pipeline(
+            device,
+            &layout,
+            &shader,
+            Some("fs_point"),
+            None,
+            wgpu::DepthBiasState::default(),
+        );
+
+        Self {
+            config,
+            texture,
+            cube_texture,
+            uniform,
+            buffer,
+            point_uniform,
+            point_buffer,
+            layers,
+            faces,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+            point_pipeline,
+            cube_arrays,
+        }
+    }
+
+    /// `source` with the point shadow cube maps declared and sampled the way
+    /// they are bound.
+    pub fn shader_source(&self, source: &str) -> String {
+        debug_assert!(
+            source.contains(Self::CUBE_ARRAY_DECLARATION)
+                && source.contains(Self::CUBE_ARRAY_SAMPLE),
+            "shader does not sample point shadows as a cube array"
+        );
+        if self.cube_arrays {
+            source.to_string()
+        } else {
+            source
+                .replace(
+                    Self::CUBE_ARRAY_DECLARATION,
+                    "var t_point_shadow: texture_depth_cube;",
+                )
+                .replace(
+                    Self::CUBE_ARRAY_SAMPLE,
+                    "textureSampleCompare(t_point_shadow, s_shadow, to_fragment, depth)",
+                )
+        }
+    }
+
+    /// Fits the shadow projections to `camera` and `light` and uploads them.
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera, light: &LightInit) {
+        match light.kind {
+            LightKind::Point => self.uniform.count = 0,
+            LightKind::Spot => {
+                self.uniform.count = 1;
+                self.uniform.splits[0] = camera.zfar;
+                self.uniform.view_proj[0] = spot_view_proj(&light.light_uniform).into();
+            }
+            LightKind::Directional => {
+                let direction = cgmath::Vector3::from(light.light_uniform.direction);
+                let count = self.config.cascades as usize;
+                let splits = cascade_splits(camera.znear, camera.zfar, count, Self::SPLIT_LAMBDA);
+
+                let mut near = camera.znear;
+                for (i, &far) in splits.iter().enumerate() {
+                    self.uniform.splits[i] = far;
+                    self.uniform.view_proj[i] = fit_cascade(
+                        camera,
+                        near,
+                        far,
+                        direction,
+                        self.config.size,
+                        Self::CASTER_DISTANCE,
+                    )
+                    .into();
+                    near = far;
+                }
+                self.uniform.count = count as u32;
+            }
+        }
+
+        let main_light = (light.kind == LightKind::Point)
+            .then_some((light.light_uniform.position, Self::MAIN_POINT_LIGHT_RANGE));
+        let (drawn, shadowed) = light.lights.drawn();
+        let point_lights = drawn[..shadowed]
+            .iter()
+            .map(|point| (point.position, point.range));
+        self.point_uniform.count = self.point_uniform.first_point_light + shadowed as u32;
+        for (i, (position, range)) in main_light.into_iter().chain(point_lights).enumerate() {
+            self.point_uniform.lights[i] = [position[0], position[1], position[2], range];
+        }
+
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+        queue.write_buffer(
+            &self.point_buffer,
+            0,
+            bytemuck::cast_slice(&[self.point_uniform]),
+        );
+
+        for (layer, view_proj) in self.layers.iter().zip(&self.uniform.view_proj) {
+            layer.write(
+                queue,
+                ShadowPassUniform {
+                    view_proj: *view_proj,
+                    light_position: light.light_uniform.position,
+                    range: 0.0,
+                },
+            );
+        }
+
+        let faces = self.point_uniform.count as usize * 6;
+        for (i, face) in self.faces.iter().take(faces).enumerate() {
+            let [x, y, z, range] = self.point_uniform.lights[i / 6];
+            let position = cgmath::Point3::new(x, y, z);
+            face.write(
+                queue,
+                ShadowPassUniform {
+                    view_proj: cube_face_view_proj(position, i % 6, range).into(),
+                    light_position: position.into(),
+                    range,
+                },
+            );
+        }
+    }
+
+    /// Records a depth-only pass per cascade and cube face in use, drawing
+    /// every instance of `model`.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, model: &Model, instances: &Instances) {
+        let cascades = self
+            .layers
+            .iter()
+            .take(self.uniform.count as usize)
+            .map(|layer| (layer, &self.pipeline));
+        let faces = self
+            .faces
+            .iter()
+            .take(self.point_uniform.count as usize * 6)
+            .map(|face| (face, &self.point_pipeline));
+
+        for (layer, pipeline) in cascades.chain(faces) {
+            let mut shadow_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("shadow pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &layer.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+
+            shadow_pass.set_pipeline(pipeline);
+            shadow_pass.set_bind_group(0, &layer.bind_group, &[]);
+            shadow_pass.set_vertex_buffer(1, instances.instance_buffer.slice(..));
+            shadow_pass.draw_model_geometry_instanced(model, 0..instances.instances.len() as u32);
+        }
+    }
+}
+
+fn create_shadow_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    fragment_entry_point: Option<&str>,
+    cull_mode: Option<wgpu::Face>,
+    bias: wgpu::DepthBiasState,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("shadow pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[model::ModelVertex::desc(), InstanceRaw::desc()],
+        },
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode,
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias,
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        fragment: fragment_entry_point.map(|entry_point| wgpu::FragmentState {
+            module: shader,
+            entry_point,
+            targets: &[],
+        }),
+        multiview: None,
+    })
+}
+
+fn supports_cube_arrays(adapter: &wgpu::Adapter) -> bool {
+    adapter
+        .get_downlevel_capabilities()
+        .flags
+        .contains(wgpu::DownlevelFlags::CUBE_ARRAY_TEXTURES)
+}
+
+/// Most point lights, the main light included, that can cast shadows on
+/// `adapter`. Without cube array textures that is only one.
+pub fn max_point_shadows(adapter: &wgpu::Adapter) -> usize {
+    if supports_cube_arrays(adapter) {
+        MAX_POINT_SHADOWS
+    } else {
+        1
+    }
+}
+
+/// Far distances of `count` cascades between `near` and `far`, using the
+/// practical split scheme: `lambda` blends logarithmic splits, which suit
+/// perspective, with uniform ones.
+pub fn cascade_splits(near: f32, far: f32, count: usize, lambda: f32) -> Vec<f32> {
+    (1..=count)
+        .map(|i| {
+            let fraction = i as f32 / count as f32;
+            let log = near * (far / near).powf(fraction);
+            let uniform = near + (far - near) * fraction;
+            lambda * log + (1.0 - lambda) * uniform
+        })
+        .collect()
+}
+
+/// Orthographic light projection covering the slice of `camera`'s frustum
+/// between `near` and `far`.
+///
+/// The projection is sized to the slice's bounding sphere and snapped to whole
+/// shadow map texels, so it does not shimmer as the camera turns or moves.
+pub fn fit_cascade(
+    camera: &Camera,
+    near: f32,
+    far: f32,
+    direction: cgmath::Vector3<f32>,
+    size: u32,
+    caster_distance: f32,
+) -> cgmath::Matrix4<f32> {
+    let forward = (camera.target - camera.eye).normalize();
+    let right = forward.cross(camera.up).normalize();
+    let up = right.cross(forward);
+    let tan_half_fovy = (camera.fovy.to_radians() / 2.0).tan();
+
+    let corners = [near, far]
+        .into_iter()
+        .flat_map(|distance| {
+            let half_height = distance * tan_half_fovy;
+            let half_width = half_height * camera.aspect;
+            [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)].map(|(x, y)| {
+                camera.eye + forward * distance + right * (x * half_width) + up * (y * half_height)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let center = cgmath::Point3::centroid(&corners);
+    let radius = corners
+        .iter()
+        .map(|corner| (corner - center).magnitude())
+        .fold(0.0, f32::max);
+    // Rounding the radius keeps the projection size constant between frames.
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    let light_up = light_up(direction);
+    let rotation = cgmath::Matrix4::look_at_rh(
+        cgmath::Point3::origin(),
+        cgmath::Point3::from_vec(direction),
+        light_up,
+    );
+    let texel = 2.0 * radius / size as f32;
+    let snapped = rotation.transform_point(center);
+    let snapped = cgmath::Point3::new(
+        (snapped.x / texel).floor() * texel,
+        (snapped.y / texel).floor() * texel,
+        snapped.z,
+    );
+    let center = rotation
+        .invert()
+        .expect("light rotation is invertible")
+        .transform_point(snapped);
+
+    let eye = center - direction * (radius + caster_distance);
+    let view = cgmath::Matrix4::look_at_rh(eye, center, light_up);
+    let proj = cgmath::ortho(
+        -radius,
+        radius,
+        -radius,
+        radius,
+        0.0,
+        2.0 * radius + caster_distance,
+    );
+
+    Camera::OPENGL_TO_WGPU_MATRIX * proj * view
+}
+
+/// Perspective projection covering a spot light's cone.
+pub fn spot_view_proj(light: &LightUniform) -> cgmath::Matrix4<f32> {
+    let position = cgmath::Point3::from(light.position);
+    let direction = cgmath::Vector3::from(light.direction);
+    let view = cgmath::Matrix4::look_at_rh(position, position + direction, light_up(direction));
+    let fovy = cgmath::Rad(light.outer_cutoff.acos() * 2.0);
+    let proj = cgmath::perspective(fovy, 1.0, 0.1, 100.0);
+
+    Camera::OPENGL_TO_WGPU_MATRIX * proj * view
+}
+
+/// Projection for `face` of a point light's cube map, in +X, -X, +Y, -Y, +Z,
+/// -Z order.
+pub fn cube_face_view_proj(
+    position: cgmath::Point3<f32>,
+    face: usize,
+    range: f32,
+) -> cgmath::Matrix4<f32> {
+    const FACES: [([f32; 3], [f32; 3]); 6] = [
+        ([1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+        ([-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+        ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, -1.0, 0.0], [0.0, 0.0, -1.0]),
+        ([0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+        ([0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+    ];
+
+    let (direction, up) = FACES[face];
+    let view = cgmath::Matrix4::look_at_rh(
+        position,
+        position + cgmath::Vector3::from(direction),
+        up.into(),
+    );
+    let proj = cgmath::perspective(cgmath::Deg(90.0), 1.0, 0.1, range);
+    // The face orientations above assume the first row of a face is at the
+    // bottom of clip space, as in OpenGL, so flip y to match wgpu.
+    let flip_y = cgmath::Matrix4::from_nonuniform_scale(1.0, -1.0, 1.0);
+
+    flip_y * Camera::OPENGL_TO_WGPU_MATRIX * proj * view
+}
+
+fn light_up(direction: cgmath::Vector3<f32>) -> cgmath::Vector3<f32> {
+    if direction.y.abs() > 0.99 {
+        cgmath::Vector3::unit_z()
+    } else {
+        cgmath::Vector3::unit_y()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> Camera {
+        Camera {
+            eye: (0.0, 2.0, 5.0).into(),
+            target: (1.0, 0.0, -3.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: 16.0 / 9.0,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+        }
+    }
+
+    /// `point` in normalized device coordinates, if it is in front of the
+    /// projection.
+    fn project(view_proj: cgmath::Matrix4<f32>, point: cgmath::Point3<f32>) -> Option<[f32; 3]> {
+        let clip = view_proj * point.to_homogeneous();
+        (clip.w > 0.0).then(|| [clip.x / clip.w, clip.y / clip.w, clip.z / clip.w])
+    }
+
+    fn inside(ndc: Option<[f32; 3]>) -> bool {
+        const EPSILON: f32 = 1e-4;
+        ndc.is_some_and(|[x, y, z]| {
+            x.abs() <= 1.0 + EPSILON
+                && y.abs() <= 1.0 + EPSILON
+                && (-EPSILON..=1.0 + EPSILON).contains(&z)
+        })
+    }
+
+    #[test]
+    fn shader_samples_point_shadows_for_replacement() {
+        let source = include_str!("shader.wgsl");
+        assert_eq!(source.matches(ShadowMap::CUBE_ARRAY_DECLARATION).count(), 1);
+        assert_eq!(source.matches(ShadowMap::CUBE_ARRAY_SAMPLE).count(), 1);
+    }
+
+    #[test]
+    fn splits_increase_from_near_to_far() {
+        for lambda in [0.0, 0.5, ShadowMap::SPLIT_LAMBDA, 1.0] {
+            let splits = cascade_splits(0.1, 100.0, 4, lambda);
+            assert_eq!(splits.len(), 4);
+            assert!(splits[0] > 0.1);
+            assert!(splits.windows(2).all(|pair| pair[0] < pair[1]));
+            assert!((splits[3] - 100.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn lambda_endpoints_are_uniform_and_logarithmic() {
+        let uniform = cascade_splits(1.0, 81.0, 4, 0.0);
+        for (split, expected) in uniform.iter().zip([21.0, 41.0, 61.0, 81.0]) {
+            assert!((split - expected).abs() < 1e-3, "{:?}", uniform);
+        }
+
+        let log = cascade_splits(1.0, 81.0, 4, 1.0);
+        for (split, expected) in log.iter().zip([3.0, 9.0, 27.0, 81.0]) {
+            assert!((split - expected).abs() < 1e-3, "{:?}", log);
+        }
+    }
+
+    #[test]
+    fn fitted_cascade_contains_its_frustum_slice() {
+        let camera = camera();
+        let forward = (camera.target - camera.eye).normalize();
+        let right = forward.cross(camera.up).normalize();
+        let up = right.cross(forward);
+        let tan_half_fovy = (camera.fovy.to_radians() / 2.0).tan();
+
+        let directions = [
+            cgmath::Vector3::new(-0.3, -1.0, -0.5).normalize(),
+            -cgmath::Vector3::unit_y(),
+            cgmath::Vector3::new(1.0, -0.2, 0.0).normalize(),
+        ];
+        for direction in directions {
+            for (near, far) in [(0.1, 4.0), (4.0, 20.0), (20.0, 100.0)] {
+                let view_proj = fit_cascade(&camera, near, far, direction, 1024, 50.0);
+                for distance in [near, far] {
+                    let half_height = distance * tan_half_fovy;
+                    let half_width = half_height * camera.aspect;
+                    for (x, y) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+                        let corner = camera.eye
+                            + forward * distance
+                            + right * (x * half_width)
+                            + up * (y * half_height);
+                        assert!(
+                            inside(project(view_proj, corner)),
+                            "{:?} outside cascade {}..{} for {:?}",
+                            corner,
+                            near,
+                            far,
+                            direction
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn spot_projection_covers_its_cone() {
+        let mut light = LightUniform::new(LightKind::Spot, [0.0, 4.0, 0.0], [1.0; 3]);
+        light.aim_at((2.0, 0.0, 0.0).into());
+        let view_proj = spot_view_proj(&light);
+
+        let position = cgmath::Point3::from(light.position);
+        let direction = cgmath::Vector3::from(light.direction);
+        let ahead = project(view_proj, position + direction * 5.0).unwrap();
+        assert!(
+            ahead[0].abs() < 1e-4 && ahead[1].abs() < 1e-4,
+            "{:?}",
+            ahead
+        );
+        assert!(project(view_proj, position - direction * 5.0).is_none());
+
+        // Just inside the outer cone, rotated about the light's right axis.
+        let angle = cgmath::Rad(light.outer_cutoff.acos() * 0.99);
+        let axis = direction.cross(cgmath::Vector3::unit_y()).normalize();
+        let edge = cgmath::Matrix3::from_axis_angle(axis, angle) * direction;
+        assert!(inside(project(view_proj, position + edge * 5.0)));
+    }
+
+    #[test]
+    fn cube_faces_look_along_their_axis() {
+        let position = cgmath::Point3::new(1.0, 2.0, 3.0);
+        let axes = [
+            cgmath::Vector3::unit_x(),
+            -cgmath::Vector3::unit_x(),
+            cgmath::Vector3::unit_y(),
+            -cgmath::Vector3::unit_y(),
+            cgmath::Vector3::unit_z(),
+            -cgmath::Vector3::unit_z(),
+        ];
+
+        for (face, axis) in axes.iter().enumerate() {
+            let view_proj = cube_face_view_proj(position, face, 20.0);
+            let center = project(view_proj, position + axis * 5.0).unwrap();
+            assert!(center[0].abs() < 1e-4 && center[1].abs() < 1e-4);
+            assert!((0.0..1.0).contains(&center[2]));
+
+            // Only this face sees the points along its axis.
+            for (other, other_axis) in axes.iter().enumerate() {
+                if other != face {
+                    assert!(!inside(project(view_proj, position + other_axis * 5.0)));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use cgmath::SquareMatrix;
+use image::Rgba32FImage;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    assets::Assets,
+    camera::Camera,
+    environment,
+    error::Result,
+    texture::{self, CubeTexture},
+};
+
+/// Where the skybox's cube map is loaded from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkyboxSource {
+    /// An equirectangular panorama.
+    Equirect(String),
+    /// Six square images, in +X, -X, +Y, -Y, +Z, -Z order.
+    Faces([String; 6]),
+}
+
+impl SkyboxSource {
+    /// Reads the six cube faces, which also light the scene.
+    pub fn load_faces(&self, assets: &Assets) -> Result<[Rgba32FImage; 6]> {
+        match self {
+            SkyboxSource::Equirect(file) => environment::load_equirect(assets, file),
+            SkyboxSource::Faces(files) => environment::load_faces(assets, files),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkyboxUniform {
+    inv_view_proj: [[f32; 4]; 4],
+}
+
+/// A cube map drawn behind everything else. It is drawn last, on the far
+/// plane, and depth tested so only the pixels no geometry covered are shaded.
+pub struct Skybox {
+    pub cube: CubeTexture,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl Skybox {
+    pub fn new(
+        device: &wgpu::Device,
+        cube: CubeTexture,
+        color_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("skybox buffer"),
+            contents: bytemuck::cast_slice(&[SkyboxUniform {
+                inv_view_proj: cgmath::Matrix4::identity().into(),
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("skybox bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("skybox bind group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&cube.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&cube.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("skybox pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("skybox shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("skybox.wgsl").into()),
+        });
+
+        let pipeline = create_skybox_pipeline(device, &layout, &shader, color_format, sample_count);
+
+        Self {
+            cube,
+            buffer,
+            bind_group,
+            layout,
+            shader,
+            pipeline,
+        }
+    }
+
+    /// Rebuilds the pipeline for a new color format or sample count.
+    pub fn rebuild(
+        &mut self,
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) {
+        self.pipeline = create_skybox_pipeline(
+            device,
+            &self.layout,
+            &self.shader,
+            color_format,
+            sample_count,
+        );
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera) {
+        let inv_view_proj = sky_view_proj(camera)
+            .invert()
+            .unwrap_or_else(cgmath::Matrix4::identity);
+        queue.write_buffer(
+            &self.buffer,
+            0,
+            bytemuck::cast_slice(&[SkyboxUniform {
+                inv_view_proj: inv_view_proj.into(),
+            }]),
+        );
+    }
+
+    /// Draws into a pass whose depth attachment already holds the scene.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+/// The camera's view projection without its translation, so the sky stays
+/// infinitely far away however the camera moves.
+pub fn sky_view_proj(camera: &Camera) -> cgmath::Matrix4<f32> {
+    let mut view = camera.build_view_matrix();
+    view.w = cgmath::Vector4::unit_w();
+    camera.build_projection_matrix() * view
+}
+
+/// Depth is tested with `LessEqual` against the far plane the pass cleared
+/// to, but never written.
+fn create_skybox_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    color_format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("skybox pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format: color_format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        multiview: None,
+    })
+}
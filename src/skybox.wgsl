@@ -0,0 +1,39 @@
+struct Skybox {
+    // Clip space back to a world space direction, from a view matrix
+    // without the camera's translation.
+    inv_view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0)
+var t_sky: texture_cube<f32>;
+@group(0) @binding(1)
+var s_sky: sampler;
+@group(0) @binding(2)
+var<uniform> skybox: Skybox;
+
+struct VertexOutput{
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) clip: vec2<f32>,
+}
+
+// One triangle covering the screen on the far plane, so it only shows where
+// no geometry was drawn.
+@vertex
+
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput{
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let clip = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(clip, 1.0, 1.0);
+    out.clip = clip;
+    return out;
+}
+
+@fragment
+
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32>{
+    let world = skybox.inv_view_proj * vec4<f32>(in.clip, 1.0, 1.0);
+    let direction = world.xyz / world.w;
+    return vec4<f32>(textureSample(t_sky, s_sky, direction).rgb, 1.0);
+}
@@ -1,92 +1,226 @@
 use wgpu::SurfaceConfiguration;
-use winit::{event::WindowEvent, window::Window};
+use winit::{
+    event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent},
+    window::Window,
+};
 
 use crate::{
-    camera::CameraInit,
+    camera::{Camera, CameraInit},
+    clock::{FixedTimestep, FrameTime},
+    environment,
+    error::{Error, Result},
+    ibl::{Ibl, IblMaps},
     instance::{InstanceRaw, Instances},
-    model::{self, DrawModel, Model, Vertex},
+    light::{Light, LightId, LightInit},
+    mipmap::MipmapGenerator,
+    model::{self, DrawLight, DrawModel, Model, Vertex},
+    settings::Settings,
+    shadow::ShadowMap,
+    skybox::Skybox,
     texture,
+    texture_cache::TextureCache,
 };
 
+pub enum RenderTarget {
+    Surface {
+        surface: wgpu::Surface,
+        window: Window,
+    },
+    Offscreen {
+        texture: texture::Texture,
+    },
+}
+
 pub struct State {
-    surface: wgpu::Surface,
+    target: RenderTarget,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
-    pub window: Window,
+    render_pipeline_source: PipelineSource,
     render_pipeline: wgpu::RenderPipeline,
-    texture_bind_group_layout: wgpu::BindGroupLayout,
-    texture_bind_group: wgpu::BindGroup,
+    light_pipeline_source: PipelineSource,
+    light_pipeline: wgpu::RenderPipeline,
+    sample_count: u32,
+    supported_sample_counts: Vec<u32>,
+    msaa_texture: Option<texture::Texture>,
+    present_modes: Vec<wgpu::PresentMode>,
     camera: CameraInit,
+    light: LightInit,
+    shadow_map: ShadowMap,
+    skybox: Option<Skybox>,
+    /// Background where nothing is drawn, covered by the skybox if there is one.
+    clear_color: wgpu::Color,
+    /// Camera at the previous fixed step, interpolated towards the current one.
+    previous_camera: Camera,
+    fixed_timestep: Option<FixedTimestep>,
     instances: Instances,
     depth_texture: texture::Texture,
     obj_model: Model,
+    texture_cache: TextureCache,
 }
 
 impl State {
-    pub async fn new(window: Window) -> Self {
+    pub const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    pub async fn new(window: Window, settings: &Settings) -> Result<Self> {
         let size = window.inner_size();
 
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::VULKAN,
-            dx12_shader_compiler: wgpu::Dx12Compiler::default(),
-        });
+        let instance = settings.adapter.create_instance();
 
-        let surface = unsafe { instance.create_surface(&window) }.unwrap();
+        let surface = unsafe { instance.create_surface(&window) }?;
 
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptionsBase {
-                power_preference: wgpu::PowerPreference::LowPower,
-                force_fallback_adapter: false,
-                compatible_surface: Some(&surface),
-            })
-            .await
-            .unwrap();
+        let adapter = settings
+            .adapter
+            .select_adapter(&instance, Some(&surface))
+            .await?;
 
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: Some("device"),
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
-                },
-                None,
-            )
-            .await
-            .unwrap();
+        let (device, queue) = Self::request_device(&adapter).await?;
 
         let surface_caps = surface.get_capabilities(&adapter);
         let format = surface_caps
             .formats
             .iter()
             .copied()
-            .filter(|f| f.describe().srgb)
-            .next()
-            .unwrap_or(surface_caps.formats[0]);
+            .find(|f| f.describe().srgb)
+            .or_else(|| surface_caps.formats.first().copied())
+            .ok_or_else(|| Error::UnsupportedSurfaceFormat(surface_caps.formats.clone()))?;
+
+        let present_mode = choose_present_mode(settings.present_mode, &surface_caps.present_modes);
 
         let config = SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format,
             width: size.width,
             height: size.height,
-            present_mode: wgpu::PresentMode::AutoVsync,
+            present_mode,
             alpha_mode: wgpu::CompositeAlphaMode::Auto,
             view_formats: vec![],
         };
 
         surface.configure(&device, &config);
 
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-        });
+        let mut state = Self::from_device(
+            &adapter,
+            device,
+            queue,
+            config,
+            RenderTarget::Surface { surface, window },
+            settings,
+        )
+        .await?;
+        state.present_modes = surface_caps.present_modes;
+
+        Ok(state)
+    }
+
+    /// Builds the same pipeline as [`State::new`] but renders into an offscreen
+    /// texture instead of a window surface, so frames can be read back with
+    /// [`State::read_frame`]. Works with a software adapter when the adapter
+    /// settings allow falling back to one.
+    pub async fn new_headless(width: u32, height: u32, settings: &Settings) -> Result<Self> {
+        let instance = settings.adapter.create_instance();
+        let adapter = settings.adapter.select_adapter(&instance, None).await?;
+
+        let (device, queue) = Self::request_device(&adapter).await?;
+
+        let config = SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: Self::OFFSCREEN_FORMAT,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::AutoVsync,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: vec![],
+        };
+
+        let texture = texture::Texture::create_render_target(&device, &config, "offscreen target");
+
+        Self::from_device(
+            &adapter,
+            device,
+            queue,
+            config,
+            RenderTarget::Offscreen { texture },
+            settings,
+        )
+        .await
+    }
+
+    async fn request_device(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue)> {
+        let device_and_queue = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("device"),
+                    // Compressed textures are decompressed on load when their
+                    // feature is missing.
+                    features: adapter.features()
+                        & (wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                            | wgpu::Features::TEXTURE_COMPRESSION_BC
+                            | wgpu::Features::TEXTURE_COMPRESSION_ETC2
+                            | wgpu::Features::TEXTURE_COMPRESSION_ASTC_LDR),
+                    limits: Self::limits(adapter),
+                },
+                None,
+            )
+            .await?;
+
+        Ok(device_and_queue)
+    }
+
+    /// The WebGPU default limits, or the first tier of lower ones the adapter
+    /// reaches. The light list falls back to a uniform array on adapters
+    /// without storage buffers.
+    fn limits(adapter: &wgpu::Adapter) -> wgpu::Limits {
+        let supported = adapter.limits();
+        let limits = [
+            wgpu::Limits::default(),
+            wgpu::Limits::downlevel_defaults(),
+            wgpu::Limits::downlevel_webgl2_defaults(),
+        ]
+        .into_iter()
+        .find(|limits| limits.check_limits(&supported))
+        .unwrap_or_else(|| supported.clone());
+        limits.using_resolution(supported)
+    }
+
+    async fn from_device(
+        adapter: &wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: SurfaceConfiguration,
+        target: RenderTarget,
+        settings: &Settings,
+    ) -> Result<Self> {
+        let size = winit::dpi::PhysicalSize::new(config.width, config.height);
+
+        let supported_sample_counts = supported_sample_counts(adapter, &device, config.format);
+        let sample_count = pick_sample_count(&supported_sample_counts, settings.sample_count);
 
         /*
            COMPONENTS
         */
 
         let camera = CameraInit::new(&config, &device);
+
+        // The skybox doubles as the environment that lights the scene. One
+        // that fails to load leaves the clear color and no image lighting.
+        let environment = settings.skybox.as_ref().and_then(|source| {
+            source
+                .load_faces(&settings.assets)
+                .map_err(|e| log::warn!("skybox not loaded: {}", e))
+                .ok()
+        });
+        let ibl = match &environment {
+            Some(faces) => {
+                let maps = IblMaps::load_or_compute(faces, settings.ibl_cache.as_deref());
+                Ibl::new(&device, &queue, &maps)
+            }
+            None => Ibl::none(&device, &queue),
+        };
+
+        let light = LightInit::new(&device, adapter, settings.light, settings.point_lights, ibl);
+        let shadow_map = ShadowMap::new(&device, adapter, settings.shadow, &light);
         let instances = Instances::new(&device);
 
         /*
@@ -97,131 +231,166 @@ impl State {
         TEXTURE SECTION
          */
 
-        let texture = texture::Texture::new(&device, &queue, "Asura.png");
-
-        let depth_texture =
-            texture::Texture::create_depth_texture(&device, &config, "depth textures");
-
-        let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("texture bind group layout"),
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-            });
+        let mut texture_cache = TextureCache::new(
+            settings.assets.clone(),
+            texture::SamplerOptions {
+                anisotropy: settings.anisotropy,
+                ..Default::default()
+            },
+            MipmapGenerator::new(&device, adapter.get_info().backend),
+        );
 
-        let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("texture bind group"),
-            layout: &texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
-                },
-            ],
+        let depth_texture = texture::Texture::create_depth_texture(
+            &device,
+            &config,
+            sample_count,
+            "depth textures",
+        );
+        let msaa_texture = (sample_count > 1).then(|| {
+            texture::Texture::create_multisampled_framebuffer(
+                &device,
+                &config,
+                sample_count,
+                "msaa framebuffer",
+            )
         });
 
+        let texture_bind_group_layout = model::Material::create_bind_group_layout(&device);
+
         /*
         END OF TEXTURE SECTION
          */
 
-        let obj_model =
-            model::Model::load_model("cube.obj", &device, &queue, &texture_bind_group_layout)
-                .await
-                .unwrap();
+        let obj_model = model::Model::load_model(
+            &settings.model,
+            &mut texture_cache,
+            &device,
+            &queue,
+            &texture_bind_group_layout,
+        )
+        .await?;
+        log::info!(
+            "{} textures loaded, using {} KiB",
+            texture_cache.len(),
+            texture_cache.memory_usage() / 1024
+        );
 
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render pipeline layout"),
-                bind_group_layouts: &[&texture_bind_group_layout, &camera.camera_bind_group_layout],
+                bind_group_layouts: &[
+                    &texture_bind_group_layout,
+                    &camera.camera_bind_group_layout,
+                    &light.light_bind_group_layout,
+                    &shadow_map.bind_group_layout,
+                ],
                 push_constant_ranges: &[],
             });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("render pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[model::ModelVertex::desc(), InstanceRaw::desc()],
-            },
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                unclipped_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: texture::Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
+        let render_pipeline_source = PipelineSource {
+            layout: render_pipeline_layout,
+            shader: device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Shader"),
+                source: wgpu::ShaderSource::Wgsl(
+                    shadow_map
+                        .shader_source(&light.lights.shader_source(include_str!("shader.wgsl")))
+                        .into(),
+                ),
             }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
+            vertex_layouts: vec![model::ModelVertex::desc(), InstanceRaw::desc()],
+        };
+        let render_pipeline = render_pipeline_source.build(&device, config.format, sample_count);
+
+        let light_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Light pipeline layout"),
+                bind_group_layouts: &[
+                    &camera.camera_bind_group_layout,
+                    &light.light_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let light_pipeline_source = PipelineSource {
+            layout: light_pipeline_layout,
+            shader: device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Light shader"),
+                source: wgpu::ShaderSource::Wgsl(
+                    light
+                        .lights
+                        .shader_source(include_str!("light.wgsl"))
+                        .into(),
+                ),
             }),
-            multiview: None,
+            vertex_layouts: vec![model::ModelVertex::desc()],
+        };
+        let light_pipeline = light_pipeline_source.build(&device, config.format, sample_count);
+
+        let skybox = environment.map(|faces| {
+            let format = environment::cube_format(adapter, &device);
+            let cube = texture::CubeTexture::from_faces(&device, &queue, &faces, format, "skybox");
+            Skybox::new(&device, cube, config.format, sample_count)
         });
 
-        Self {
-            surface,
+        Ok(Self {
+            target,
             device,
             queue,
             config,
             size,
-            window,
+            render_pipeline_source,
             render_pipeline,
-            texture_bind_group_layout,
-            texture_bind_group,
+            light_pipeline_source,
+            light_pipeline,
+            sample_count,
+            supported_sample_counts,
+            msaa_texture,
+            previous_camera: camera.camera,
+            fixed_timestep: settings
+                .fixed_update_rate
+                .and_then(FixedTimestep::from_rate),
             camera,
+            light,
+            shadow_map,
+            skybox,
+            clear_color: settings.clear_color,
             instances,
             depth_texture,
             obj_model,
-        }
+            texture_cache,
+            present_modes: Vec::new(),
+        })
     }
 
-    pub fn update(&mut self) {
-        self.camera
-            .camera_controller
-            .update_camera(&mut self.camera.camera);
+    /// Advances the simulation by one frame. With a fixed timestep the
+    /// simulation runs in whole steps and the camera is interpolated between
+    /// the last two of them.
+    pub fn update(&mut self, time: FrameTime) {
+        let camera = match self.fixed_timestep.as_mut() {
+            Some(timestep) => {
+                let steps = timestep.advance(time.delta);
+                let step = timestep.step();
+                let alpha = timestep.alpha();
+
+                for _ in 0..steps {
+                    self.previous_camera = self.camera.camera;
+                    self.simulate(step);
+                }
+
+                self.camera.camera.interpolate(&self.previous_camera, alpha)
+            }
+            None => {
+                self.simulate(time.delta);
+                self.camera.camera
+            }
+        };
 
-        self.camera
-            .camera_uniform
-            .update_view_proj(&mut self.camera.camera);
+        self.camera.camera_uniform.update_view_proj(&camera);
+        self.light.write(&self.queue);
+        self.shadow_map.update(&self.queue, &camera, &self.light);
+        if let Some(skybox) = &self.skybox {
+            skybox.update(&self.queue, &camera);
+        }
 
         self.queue.write_buffer(
             &self.camera.camera_buffer,
@@ -230,8 +399,193 @@ impl State {
         );
     }
 
+    fn simulate(&mut self, dt: std::time::Duration) {
+        self.camera
+            .camera_controller
+            .update_camera(&mut self.camera.camera, dt.as_secs_f32());
+        self.light.update(dt.as_secs_f32());
+    }
+
+    pub fn window(&self) -> Option<&Window> {
+        match &self.target {
+            RenderTarget::Surface { window, .. } => Some(window),
+            RenderTarget::Offscreen { .. } => None,
+        }
+    }
+
     pub fn input(&mut self, event: &WindowEvent) -> bool {
-        self.camera.camera_controller.process_events(event)
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::M),
+                        ..
+                    },
+                ..
+            } => {
+                self.cycle_sample_count();
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::V),
+                        ..
+                    },
+                ..
+            } => {
+                self.toggle_vsync();
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::C),
+                        ..
+                    },
+                ..
+            } => {
+                self.toggle_cascade_debug();
+                true
+            }
+            _ => self.camera.camera_controller.process_events(event),
+        }
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    pub fn supported_sample_counts(&self) -> &[u32] {
+        &self.supported_sample_counts
+    }
+
+    /// Switches to the largest supported sample count not above `count` and
+    /// rebuilds the pipeline and render targets. Returns the count in use.
+    pub fn set_sample_count(&mut self, count: u32) -> u32 {
+        let sample_count = pick_sample_count(&self.supported_sample_counts, count);
+        if sample_count == self.sample_count {
+            return sample_count;
+        }
+
+        self.sample_count = sample_count;
+        self.render_pipeline =
+            self.render_pipeline_source
+                .build(&self.device, self.config.format, sample_count);
+        self.light_pipeline =
+            self.light_pipeline_source
+                .build(&self.device, self.config.format, sample_count);
+        if let Some(skybox) = &mut self.skybox {
+            skybox.rebuild(&self.device, self.config.format, sample_count);
+        }
+        self.create_frame_targets();
+
+        log::info!("MSAA sample count set to {}", sample_count);
+        sample_count
+    }
+
+    fn cycle_sample_count(&mut self) {
+        let next = self
+            .supported_sample_counts
+            .iter()
+            .copied()
+            .find(|&count| count > self.sample_count)
+            .unwrap_or(1);
+        self.set_sample_count(next);
+    }
+
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        self.config.present_mode
+    }
+
+    /// Present modes the surface supports. Empty for headless states.
+    pub fn supported_present_modes(&self) -> &[wgpu::PresentMode] {
+        &self.present_modes
+    }
+
+    /// Reconfigures the surface with `mode`, or the closest supported mode if
+    /// the surface does not offer it. Returns the mode in use.
+    pub fn set_present_mode(&mut self, mode: wgpu::PresentMode) -> wgpu::PresentMode {
+        let RenderTarget::Surface { surface, .. } = &self.target else {
+            return self.config.present_mode;
+        };
+
+        let present_mode = choose_present_mode(mode, &self.present_modes);
+        if present_mode != self.config.present_mode {
+            self.config.present_mode = present_mode;
+            if self.config.width > 0 && self.config.height > 0 {
+                surface.configure(&self.device, &self.config);
+            }
+            log::info!("present mode set to {:?}", present_mode);
+        }
+
+        present_mode
+    }
+
+    fn toggle_vsync(&mut self) {
+        let mode = match self.config.present_mode {
+            wgpu::PresentMode::Fifo | wgpu::PresentMode::FifoRelaxed => {
+                wgpu::PresentMode::AutoNoVsync
+            }
+            _ => wgpu::PresentMode::AutoVsync,
+        };
+        self.set_present_mode(mode);
+    }
+
+    /// Tints each shadow cascade a different color, or stops doing so.
+    pub fn toggle_cascade_debug(&mut self) {
+        self.shadow_map.uniform.debug ^= 1;
+        log::info!(
+            "shadow cascade debug view {}",
+            if self.shadow_map.uniform.debug != 0 {
+                "on"
+            } else {
+                "off"
+            }
+        );
+    }
+
+    /// Adds a light shining alongside the main light from the next frame on,
+    /// returning a handle to change or remove it with. Adapters draw up to
+    /// [`LightList::capacity`](crate::light::LightList::capacity) lights.
+    pub fn add_light(&mut self, light: Light) -> LightId {
+        self.light.lights.add(light)
+    }
+
+    /// The light `id` refers to, for changes that show from the next frame
+    /// on, or `None` once it has been removed.
+    pub fn light_mut(&mut self, id: LightId) -> Option<&mut Light> {
+        self.light.lights.get_mut(id)
+    }
+
+    pub fn remove_light(&mut self, id: LightId) -> Option<Light> {
+        self.light.lights.remove(id)
+    }
+
+    /// Textures shared between the loaded materials. Dropping a model and
+    /// calling [`TextureCache::evict_unused`] frees the ones it used alone.
+    pub fn texture_cache(&mut self) -> &mut TextureCache {
+        &mut self.texture_cache
+    }
+
+    fn create_frame_targets(&mut self) {
+        self.depth_texture = texture::Texture::create_depth_texture(
+            &self.device,
+            &self.config,
+            self.sample_count,
+            "depth texture",
+        );
+        self.msaa_texture = (self.sample_count > 1).then(|| {
+            texture::Texture::create_multisampled_framebuffer(
+                &self.device,
+                &self.config,
+                self.sample_count,
+                "msaa framebuffer",
+            )
+        });
     }
 
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -239,41 +593,64 @@ impl State {
             self.size = new_size;
             self.config.height = new_size.height;
             self.config.width = new_size.width;
-            self.surface.configure(&self.device, &self.config);
 
-            self.depth_texture = texture::Texture::create_depth_texture(
-                &self.device,
-                &self.config,
-                "resized depth texture",
-            );
+            match &mut self.target {
+                RenderTarget::Surface { surface, .. } => {
+                    surface.configure(&self.device, &self.config)
+                }
+                RenderTarget::Offscreen { texture } => {
+                    *texture = texture::Texture::create_render_target(
+                        &self.device,
+                        &self.config,
+                        "resized offscreen target",
+                    )
+                }
+            }
+
+            self.create_frame_targets();
         }
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-        let view = output.texture.create_view(&wgpu::TextureViewDescriptor {
-            label: Some("render texture view"),
-            ..Default::default()
-        });
+        match &self.target {
+            RenderTarget::Surface { surface, .. } => {
+                let output = surface.get_current_texture()?;
+                let view = output.texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("render texture view"),
+                    ..Default::default()
+                });
+
+                self.draw(&view);
+                output.present();
+            }
+            RenderTarget::Offscreen { texture } => self.draw(&texture.view),
+        }
+
+        Ok(())
+    }
 
+    fn draw(&self, view: &wgpu::TextureView) {
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("render encoder"),
             });
 
+        self.shadow_map
+            .render(&mut encoder, &self.obj_model, &self.instances);
+
+        let (view, resolve_target) = match &self.msaa_texture {
+            Some(msaa_texture) => (&msaa_texture.view, Some(view)),
+            None => (view, None),
+        };
+
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("render pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &view,
-                resolve_target: None,
+                view,
+                resolve_target,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: 0.2,
-                        g: 0.3,
-                        b: 0.4,
-                        a: 1.0,
-                    }),
+                    load: wgpu::LoadOp::Clear(self.clear_color),
                     store: true,
                 },
             })],
@@ -287,24 +664,264 @@ impl State {
             }),
         });
 
+        render_pass.set_pipeline(&self.light_pipeline);
+        render_pass.draw_light_model_instanced(
+            &self.obj_model,
+            0..1 + self.light.lights.len().min(self.light.lights.capacity()) as u32,
+            &self.camera.camera_bind_group,
+            &self.light.light_bind_group,
+        );
+
         render_pass.set_vertex_buffer(1, self.instances.instance_buffer.slice(..));
         render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(3, &self.shadow_map.bind_group, &[]);
 
-        let mesh = &self.obj_model.meshes[0];
-        let material = &self.obj_model.materials[mesh.material];
-
-        render_pass.draw_mesh_instanced(
-            mesh,
-            material,
+        render_pass.draw_model_instanced(
+            &self.obj_model,
             0..self.instances.instances.len() as u32,
             &self.camera.camera_bind_group,
+            &self.light.light_bind_group,
         );
 
+        if let Some(skybox) = &self.skybox {
+            skybox.draw(&mut render_pass);
+        }
+
         drop(render_pass);
 
         self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
+    }
 
-        Ok(())
+    /// Copies the last rendered offscreen frame back to the CPU.
+    pub fn read_frame(&self) -> Result<image::RgbaImage> {
+        let RenderTarget::Offscreen { texture } = &self.target else {
+            return Err(Error::NoOffscreenTarget);
+        };
+
+        let width = self.config.width;
+        let height = self.config.height;
+        let unpadded_bytes_per_row = 4 * width;
+        let padded_bytes_per_row = unpadded_bytes_per_row
+            .div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("frame readback buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("readback encoder"),
+            });
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: std::num::NonZeroU32::new(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            sender.send(result).unwrap();
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        // A dropped callback means the map never completed.
+        receiver.recv().map_err(|_| wgpu::BufferAsyncError)??;
+
+        let data = slice.get_mapped_range();
+        let pixels = data
+            .chunks(padded_bytes_per_row as usize)
+            .flat_map(|row| &row[..unpadded_bytes_per_row as usize])
+            .copied()
+            .collect::<Vec<_>>();
+        drop(data);
+        buffer.unmap();
+
+        let len = pixels.len();
+        image::RgbaImage::from_raw(width, height, pixels).ok_or(Error::FrameSize {
+            width,
+            height,
+            len,
+        })
+    }
+
+    pub fn save_frame(&self, path: &str) -> Result<()> {
+        self.read_frame()?
+            .save(path)
+            .map_err(|source| Error::Encode {
+                path: path.to_string(),
+                source,
+            })
+    }
+}
+
+/// Everything needed to (re)build a render pipeline when the sample count
+/// changes.
+struct PipelineSource {
+    layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    vertex_layouts: Vec<wgpu::VertexBufferLayout<'static>>,
+}
+
+impl PipelineSource {
+    fn build(
+        &self,
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> wgpu::RenderPipeline {
+        create_render_pipeline(
+            device,
+            &self.layout,
+            color_format,
+            Some(texture::Texture::DEPTH_FORMAT),
+            &self.vertex_layouts,
+            &self.shader,
+            sample_count,
+        )
     }
 }
+
+pub fn create_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+    depth_format: Option<wgpu::TextureFormat>,
+    vertex_layouts: &[wgpu::VertexBufferLayout],
+    shader: &wgpu::ShaderModule,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("render pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: vertex_layouts,
+        },
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+            format,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format: color_format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        multiview: None,
+    })
+}
+
+/// Sample counts usable for both the color target and the depth buffer.
+///
+/// Without `TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES` wgpu only allows the
+/// counts WebGPU guarantees, so the adapter's own features are only trusted
+/// when that feature is enabled or the adapter is downlevel anyway.
+fn supported_sample_counts(
+    adapter: &wgpu::Adapter,
+    device: &wgpu::Device,
+    color_format: wgpu::TextureFormat,
+) -> Vec<u32> {
+    let adapter_specific = device
+        .features()
+        .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+        || !adapter.get_downlevel_capabilities().is_webgpu_compliant();
+
+    let flags = |format: wgpu::TextureFormat| {
+        if adapter_specific {
+            adapter.get_texture_format_features(format).flags
+        } else {
+            format.describe().guaranteed_format_features.flags
+        }
+    };
+
+    let color = flags(color_format);
+    let depth = flags(texture::Texture::DEPTH_FORMAT);
+
+    [1, 2, 4, 8]
+        .into_iter()
+        .filter(|&count| {
+            count == 1
+                || (color.sample_count_supported(count)
+                    && color.contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE)
+                    && depth.sample_count_supported(count))
+        })
+        .collect()
+}
+
+/// Resolves `requested` against the modes the surface supports. The `Auto`
+/// modes and any unsupported mode fall back in order of preference, ending at
+/// `Fifo`, which every surface supports.
+fn choose_present_mode(
+    requested: wgpu::PresentMode,
+    supported: &[wgpu::PresentMode],
+) -> wgpu::PresentMode {
+    use wgpu::PresentMode::*;
+
+    let preferences: &[wgpu::PresentMode] = match requested {
+        AutoVsync => &[FifoRelaxed, Fifo],
+        AutoNoVsync | Immediate => &[Immediate, Mailbox, Fifo],
+        Mailbox => &[Mailbox, Immediate, Fifo],
+        FifoRelaxed => &[FifoRelaxed, Fifo],
+        Fifo => &[Fifo],
+    };
+
+    preferences
+        .iter()
+        .copied()
+        .find(|mode| supported.contains(mode))
+        .unwrap_or(Fifo)
+}
+
+fn pick_sample_count(supported: &[u32], requested: u32) -> u32 {
+    supported
+        .iter()
+        .copied()
+        .filter(|&count| count <= requested)
+        .max()
+        .unwrap_or(1)
+}
@@ -1,19 +1,150 @@
 use image::{EncodableLayout, GenericImageView};
 
+use crate::{
+    assets::Assets,
+    decompress,
+    error::{Error, Result},
+    mipmap::{self, MipmapGenerator},
+    texture_container::{self, ContainerImage},
+};
+
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
+    pub format: wgpu::TextureFormat,
+}
+
+/// How a texture is filtered and wrapped when sampled. Hashable, so it can
+/// be part of a [`crate::texture_cache::TextureKey`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SamplerOptions {
+    pub address_mode_u: wgpu::AddressMode,
+    pub address_mode_v: wgpu::AddressMode,
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+    /// Maximum anisotropic filtering samples, 1 for none. Must be 1, 2, 4, 8
+    /// or 16, and is ignored by adapters without anisotropic filtering.
+    pub anisotropy: u8,
+}
+
+impl Default for SamplerOptions {
+    /// Trilinear filtering without anisotropy.
+    fn default() -> Self {
+        Self {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            anisotropy: 1,
+        }
+    }
+}
+
+impl SamplerOptions {
+    pub fn create_sampler(&self, device: &wgpu::Device, label: &str) -> wgpu::Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(label),
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            anisotropy_clamp: std::num::NonZeroU8::new(self.anisotropy).filter(|n| n.get() > 1),
+            ..Default::default()
+        })
+    }
+}
+
+/// What a texture's texels hold, which decides the format it's stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextureKind {
+    /// Colors authored in sRGB, decoded to linear when sampled.
+    Color,
+    /// Tangent space normals, which would be bent by an sRGB decode.
+    Normal,
+    /// Linear values such as roughness, metalness, occlusion or masks.
+    Data,
+    /// Linear colors that can go above 1, stored as half floats.
+    Hdr,
+}
+
+impl TextureKind {
+    pub fn format(self) -> wgpu::TextureFormat {
+        match self {
+            TextureKind::Color => wgpu::TextureFormat::Rgba8UnormSrgb,
+            TextureKind::Normal | TextureKind::Data => wgpu::TextureFormat::Rgba8Unorm,
+            TextureKind::Hdr => wgpu::TextureFormat::Rgba16Float,
+        }
+    }
 }
 
 impl Texture {
-    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, file: &str) -> Self {
-        let binding = String::from("res/".to_owned() + file);
-        let image_file = std::fs::read(binding).unwrap();
+    /// Loads an image, or a KTX2 or DDS texture, from `assets` in the format
+    /// for `kind`. See [`Self::from_image`] and [`Self::from_container`] for
+    /// where their mip levels come from.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        assets: &Assets,
+        file: &str,
+        kind: TextureKind,
+        mipmaps: Option<&MipmapGenerator>,
+    ) -> Result<Self> {
+        let image_file = assets.read(file)?;
         let bytes = image_file.as_bytes();
-        let image = image::load_from_memory(bytes).unwrap();
+        if texture_container::is_container(bytes) {
+            let image = texture_container::parse(file, bytes)?;
+            return Self::from_container(device, queue, &image, file, kind);
+        }
+
+        let image = image::load_from_memory(bytes).map_err(|source| Error::Decode {
+            path: file.to_string(),
+            source,
+        })?;
+
+        Ok(Self::from_image(device, queue, &image, file, kind, mipmaps))
+    }
+
+    /// A 1x1 texture of a single color, used where a material has no map.
+    pub fn from_color(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        color: [u8; 4],
+        kind: TextureKind,
+        label: &str,
+    ) -> Self {
+        let image =
+            image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba(color)));
+        Self::from_image(device, queue, &image, label, kind, None)
+    }
+
+    /// Normal map that leaves the surface normal untouched.
+    pub fn flat_normal(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        Self::from_color(
+            device,
+            queue,
+            [128, 128, 255, 255],
+            TextureKind::Normal,
+            "flat normal map",
+        )
+    }
+
+    /// Uploads `image` with a full mip chain. The levels below the first are
+    /// rendered by `mipmaps` when it supports the texture's format, and
+    /// built on the CPU otherwise.
+    pub fn from_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &image::DynamicImage,
+        label: &str,
+        kind: TextureKind,
+        mipmaps: Option<&MipmapGenerator>,
+    ) -> Self {
         let dimensions = image.dimensions();
-        let rgba = image.to_rgba8();
 
         let size = wgpu::Extent3d {
             width: dimensions.0,
@@ -21,45 +152,289 @@ impl Texture {
             depth_or_array_layers: 1,
         };
 
+        let format = kind.format();
+
+        let mip_level_count = mipmap::mip_level_count(dimensions.0, dimensions.1);
+        let generator = mipmaps.filter(|m| mip_level_count > 1 && m.supports(format));
+
+        let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+        if generator.is_some() {
+            usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+        }
+
         let texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("texture"),
-            mip_level_count: 1,
+            label: Some(label),
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            format,
             size,
+            usage,
+            view_formats: &[],
+        });
+
+        match kind {
+            TextureKind::Hdr => {
+                let rgba = image.to_rgba32f();
+                Self::write_level(
+                    queue,
+                    &texture,
+                    format,
+                    (0, 0),
+                    dimensions,
+                    &half_float_bytes(&rgba),
+                );
+                if generator.is_none() {
+                    for (level, mip) in mipmap::generate_hdr_mips(&rgba).iter().enumerate() {
+                        let bytes = half_float_bytes(mip);
+                        Self::write_level(
+                            queue,
+                            &texture,
+                            format,
+                            (level as u32 + 1, 0),
+                            mip.dimensions(),
+                            &bytes,
+                        );
+                    }
+                }
+            }
+            _ => {
+                let rgba = image.to_rgba8();
+                Self::write_level(queue, &texture, format, (0, 0), dimensions, &rgba);
+                if generator.is_none() {
+                    let srgb = kind == TextureKind::Color;
+                    for (level, mip) in mipmap::generate_mips(&rgba, srgb).iter().enumerate() {
+                        Self::write_level(
+                            queue,
+                            &texture,
+                            format,
+                            (level as u32 + 1, 0),
+                            mip.dimensions(),
+                            mip,
+                        );
+                    }
+                }
+            }
+        }
+        if let Some(generator) = generator {
+            generator.generate(device, queue, &texture, format);
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Texture view in texture"),
+            ..Default::default()
+        });
+
+        let sampler =
+            SamplerOptions::default().create_sampler(device, "Texture sampler in texture");
+
+        Self {
+            texture,
+            view,
+            sampler,
+            format,
+        }
+    }
+
+    /// Uploads a KTX2 or DDS texture with the mip levels it was saved with.
+    /// `kind` picks between the sRGB and linear variant of its format, as it
+    /// does for images. Formats the device can't sample are first decompressed
+    /// (to RGBA16 floats for BC6H, RGBA8 otherwise), and uncompressed
+    /// textures saved without mips get them built on the CPU.
+    pub fn from_container(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &ContainerImage,
+        label: &str,
+        kind: TextureKind,
+    ) -> Result<Self> {
+        let mut format = match kind {
+            TextureKind::Color => image.format.add_srgb_suffix(),
+            _ => image.format.remove_srgb_suffix(),
+        };
+        let mut levels = image.levels.clone();
+
+        // Compressed textures need their feature enabled, and wgpu only takes
+        // them in whole blocks.
+        let info = format.describe();
+        let (block_width, block_height) = info.block_dimensions;
+        let supported = device.features().contains(info.required_features)
+            && image.width.is_multiple_of(block_width as u32)
+            && image.height.is_multiple_of(block_height as u32);
+        if !supported {
+            let compressed = format;
+            for (level, data) in levels.iter_mut().enumerate() {
+                let (width, height) = image.level_size(level);
+                let (decompressed, texels) =
+                    decompress::decompress(compressed, width, height, data).ok_or_else(|| {
+                        Error::TextureContainer {
+                            path: label.to_string(),
+                            reason: format!(
+                                "no support for {:?} on this device or the CPU",
+                                compressed
+                            ),
+                        }
+                    })?;
+                format = decompressed;
+                *data = texels;
+            }
+        }
+
+        // Full floats can't be filtered everywhere, so store them as halves.
+        if format == wgpu::TextureFormat::Rgba32Float {
+            format = wgpu::TextureFormat::Rgba16Float;
+            for data in &mut levels {
+                *data = data
+                    .chunks_exact(4)
+                    .flat_map(|value| {
+                        let value = f32::from_le_bytes(value.try_into().unwrap());
+                        half::f16::from_f32(value).to_le_bytes()
+                    })
+                    .collect();
+            }
+        }
+
+        if levels.len() == 1 && format.remove_srgb_suffix() == wgpu::TextureFormat::Rgba8Unorm {
+            let base = image::RgbaImage::from_raw(image.width, image.height, levels[0].clone())
+                .expect("level 0 holds the whole image");
+            let srgb = format.describe().srgb;
+            levels.extend(
+                mipmap::generate_mips(&base, srgb)
+                    .into_iter()
+                    .map(|mip| mip.into_raw()),
+            );
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: image.width,
+                height: image.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: levels.len() as u32,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
             view_formats: &[],
         });
 
+        for (level, data) in levels.iter().enumerate() {
+            let size = image.level_size(level);
+            Self::write_level(queue, &texture, format, (level as u32, 0), size, data);
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Texture view in texture"),
+            ..Default::default()
+        });
+
+        let sampler =
+            SamplerOptions::default().create_sampler(device, "Texture sampler in texture");
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+            format,
+        })
+    }
+
+    /// Uploads one mip level of one layer, `width` by `height` pixels, of
+    /// tightly packed rows of texels or compressed blocks.
+    fn write_level(
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+        format: wgpu::TextureFormat,
+        (level, layer): (u32, u32),
+        (width, height): (u32, u32),
+        bytes: &[u8],
+    ) {
+        // Copies of compressed textures cover whole blocks, even where the
+        // level is smaller than one.
+        let (block_width, block_height) = format.describe().block_dimensions;
+        let width = width.next_multiple_of(block_width as u32);
+        let height = height.next_multiple_of(block_height as u32);
+        let rows = height / block_height as u32;
+
         queue.write_texture(
             wgpu::ImageCopyTextureBase {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
+                texture,
+                mip_level: level,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: layer,
+                },
                 aspect: wgpu::TextureAspect::All,
             },
-            &rgba,
+            bytes,
             wgpu::ImageDataLayout {
                 offset: 0,
-                bytes_per_row: std::num::NonZeroU32::new(4 * dimensions.0),
-                rows_per_image: std::num::NonZeroU32::new(dimensions.1),
+                bytes_per_row: std::num::NonZeroU32::new(bytes.len() as u32 / rows),
+                rows_per_image: std::num::NonZeroU32::new(rows),
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
             },
-            size,
         );
+    }
+
+    /// Bytes of GPU memory taken up by every mip level and layer.
+    pub fn memory_size(&self) -> u64 {
+        let info = self.format.describe();
+        let (block_width, block_height) = info.block_dimensions;
+        let size = self.texture.size();
+
+        (0..self.texture.mip_level_count())
+            .map(|level| {
+                let width = (size.width >> level).max(1).div_ceil(block_width as u32);
+                let height = (size.height >> level).max(1).div_ceil(block_height as u32);
+                width as u64 * height as u64 * info.block_size as u64
+            })
+            .sum::<u64>()
+            * size.depth_or_array_layers as u64
+    }
+
+    pub fn create_render_target(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        label: &str,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor {
-            label: Some("Texture view in texture"),
+            label: Some("render target view"),
             ..Default::default()
         });
 
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            label: Some("Texture sampler in texture"),
+            label: Some("render target sampler"),
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
             mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Linear,
             mipmap_filter: wgpu::FilterMode::Nearest,
             ..Default::default()
         });
@@ -68,6 +443,50 @@ impl Texture {
             texture,
             view,
             sampler,
+            format: config.format,
+        }
+    }
+
+    /// Color target that is rendered into with `sample_count` samples and then
+    /// resolved into the swapchain or offscreen view.
+    pub fn create_multisampled_framebuffer(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        label: &str,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("multisampled framebuffer view"),
+            ..Default::default()
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("multisampled framebuffer sampler"),
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+            format: config.format,
         }
     }
 
@@ -76,6 +495,7 @@ impl Texture {
     pub fn create_depth_texture(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
         label: &str,
     ) -> Self {
         let size = wgpu::Extent3d {
@@ -84,14 +504,22 @@ impl Texture {
             depth_or_array_layers: 1,
         };
 
+        // Multisampled depth is never sampled, and some backends cannot create
+        // multisampled textures that are also bindable.
+        let usage = if sample_count > 1 {
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+        } else {
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+        };
+
         let desc = wgpu::TextureDescriptor {
             label: Some(label),
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            usage,
             view_formats: &[],
         };
 
@@ -102,8 +530,56 @@ impl Texture {
             ..Default::default()
         });
 
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            label: Some("depth sampler"),
+        let sampler = Self::create_comparison_sampler(device, "depth sampler");
+
+        Self {
+            texture,
+            view,
+            sampler,
+            format: Self::DEPTH_FORMAT,
+        }
+    }
+
+    /// An array of square depth textures rendered from a light's point of view
+    /// and sampled with its comparison sampler in the main pass. `view` covers
+    /// every layer.
+    pub fn create_shadow_map(device: &wgpu::Device, size: u32, layers: u32, label: &str) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: layers,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("shadow map view"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        let sampler = Self::create_comparison_sampler(device, "shadow sampler");
+
+        Self {
+            texture,
+            view,
+            sampler,
+            format: Self::DEPTH_FORMAT,
+        }
+    }
+
+    /// Linear filtering on a comparison sampler blends the results of the
+    /// four nearest depth tests, which softens shadow edges for free.
+    fn create_comparison_sampler(device: &wgpu::Device, label: &str) -> wgpu::Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(label),
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
@@ -114,12 +590,157 @@ impl Texture {
             lod_max_clamp: 100.0,
             compare: Some(wgpu::CompareFunction::LessEqual),
             ..Default::default()
+        })
+    }
+}
+
+/// Cube map textures, stored as an array of six-layer cubes in +X, -X, +Y,
+/// -Y, +Z, -Z face order.
+pub struct CubeTexture {
+    pub texture: wgpu::Texture,
+    /// View of every cube, for sampling.
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    pub format: wgpu::TextureFormat,
+}
+
+impl CubeTexture {
+    /// A single cube map of linear colors, such as an environment, from six
+    /// square faces in +X, -X, +Y, -Y, +Z, -Z order. `format` is
+    /// `Rgba16Float` or `Rgba32Float`. Each face gets a mip chain built on
+    /// the CPU, so rough reflections can sample blurrier levels.
+    pub fn from_faces(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        faces: &[image::Rgba32FImage; 6],
+        format: wgpu::TextureFormat,
+        label: &str,
+    ) -> Self {
+        let levels = mipmap::generate_cube_mips(faces);
+        Self::from_levels(device, queue, &levels, format, label)
+    }
+
+    /// [`Self::from_faces`] with every mip level given, largest first. Each
+    /// level must be half the size of the one before it.
+    pub fn from_levels(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        levels: &[[image::Rgba32FImage; 6]],
+        format: wgpu::TextureFormat,
+        label: &str,
+    ) -> Self {
+        let size = levels[0][0].width();
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: levels.len() as u32,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        let bytes = |image: &image::Rgba32FImage| match format {
+            wgpu::TextureFormat::Rgba32Float => bytemuck::cast_slice(image.as_raw()).to_vec(),
+            wgpu::TextureFormat::Rgba16Float => half_float_bytes(image),
+            _ => panic!("cube maps of colors can't be {:?}", format),
+        };
+        for (level, faces) in levels.iter().enumerate() {
+            for (layer, face) in faces.iter().enumerate() {
+                Texture::write_level(
+                    queue,
+                    &texture,
+                    format,
+                    (level as u32, layer as u32),
+                    face.dimensions(),
+                    &bytes(face),
+                );
+            }
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("cube view"),
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
         });
 
+        let sampler = SamplerOptions::default().create_sampler(device, "cube sampler");
+
         Self {
             texture,
             view,
             sampler,
+            format,
         }
     }
+
+    /// An array of `cubes` depth cube maps holding each texel's distance from
+    /// the light, divided by the light's range. Sampled with a comparison
+    /// sampler like [`Texture::create_shadow_map`]. A single cube is viewed
+    /// as a plain cube map, for adapters without cube arrays.
+    pub fn create_depth_cube_array(
+        device: &wgpu::Device,
+        size: u32,
+        cubes: u32,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 6 * cubes,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Texture::DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("depth cube array view"),
+            dimension: Some(if cubes == 1 {
+                wgpu::TextureViewDimension::Cube
+            } else {
+                wgpu::TextureViewDimension::CubeArray
+            }),
+            ..Default::default()
+        });
+
+        let sampler = Texture::create_comparison_sampler(device, "depth cube sampler");
+
+        Self {
+            texture,
+            view,
+            sampler,
+            format: Texture::DEPTH_FORMAT,
+        }
+    }
+
+    /// A view of one face of one cube, for rendering into.
+    pub fn face_view(&self, cube: u32, face: u32) -> wgpu::TextureView {
+        self.texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("cube face view"),
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_array_layer: cube * 6 + face,
+            array_layer_count: std::num::NonZeroU32::new(1),
+            ..Default::default()
+        })
+    }
+}
+
+/// `image` as `Rgba16Float` texels.
+fn half_float_bytes(image: &image::Rgba32FImage) -> Vec<u8> {
+    image
+        .as_raw()
+        .iter()
+        .flat_map(|&value| half::f16::from_f32(value).to_le_bytes())
+        .collect()
 }
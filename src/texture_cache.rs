@@ -0,0 +1,168 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    assets::Assets,
+    error::Result,
+    mipmap::MipmapGenerator,
+    texture::{SamplerOptions, Texture, TextureKind},
+};
+
+/// Identifies a cached texture. The same file loaded as different kinds, or
+/// with different sampler settings, is cached separately.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TextureKey {
+    /// Asset path, or a made up name for textures that aren't files.
+    pub path: String,
+    pub kind: TextureKind,
+    pub sampler: SamplerOptions,
+}
+
+/// Loads each texture once and hands out shared handles to it, so materials
+/// using the same file share one GPU texture.
+pub struct TextureCache {
+    assets: Assets,
+    sampler: SamplerOptions,
+    mipmaps: MipmapGenerator,
+    textures: HashMap<TextureKey, Arc<Texture>>,
+}
+
+impl TextureCache {
+    pub fn new(assets: Assets, sampler: SamplerOptions, mipmaps: MipmapGenerator) -> Self {
+        Self {
+            assets,
+            sampler,
+            mipmaps,
+            textures: HashMap::new(),
+        }
+    }
+
+    /// Where textures, and the models referencing them, are read from.
+    pub fn assets(&self) -> &Assets {
+        &self.assets
+    }
+
+    /// Sampler settings for textures whose model doesn't choose its own.
+    pub fn sampler(&self) -> SamplerOptions {
+        self.sampler
+    }
+
+    /// Renders the mip levels of textures loaded into the cache.
+    pub fn mipmaps(&self) -> &MipmapGenerator {
+        &self.mipmaps
+    }
+
+    pub fn get(&self, key: &TextureKey) -> Option<Arc<Texture>> {
+        self.textures.get(key).cloned()
+    }
+
+    /// Caches `texture` under `key`, replacing any texture already there.
+    pub fn insert(&mut self, key: TextureKey, texture: Texture) -> Arc<Texture> {
+        let texture = Arc::new(texture);
+        self.textures.insert(key, texture.clone());
+        texture
+    }
+
+    /// The cached texture for `key`, created by `load` on first use. Failed
+    /// loads aren't cached.
+    pub fn get_or_load(
+        &mut self,
+        key: TextureKey,
+        load: impl FnOnce(&Self) -> Result<Texture>,
+    ) -> Result<Arc<Texture>> {
+        if let Some(texture) = self.get(&key) {
+            return Ok(texture);
+        }
+
+        let texture = load(self)?;
+        Ok(self.insert(key, texture))
+    }
+
+    /// The image at asset path `path`, see [`Texture::new`].
+    pub fn load(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &str,
+        kind: TextureKind,
+        sampler: SamplerOptions,
+    ) -> Result<Arc<Texture>> {
+        let key = TextureKey {
+            path: path.to_string(),
+            kind,
+            sampler,
+        };
+        self.get_or_load(key, |cache| {
+            let mut texture = Texture::new(
+                device,
+                queue,
+                &cache.assets,
+                path,
+                kind,
+                Some(&cache.mipmaps),
+            )?;
+            if sampler != SamplerOptions::default() {
+                texture.sampler = sampler.create_sampler(device, path);
+            }
+            Ok(texture)
+        })
+    }
+
+    /// A shared 1x1 texture of a single color, see [`Texture::from_color`].
+    pub fn from_color(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        color: [u8; 4],
+        kind: TextureKind,
+    ) -> Arc<Texture> {
+        let [r, g, b, a] = color;
+        let key = TextureKey {
+            path: format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a),
+            kind,
+            sampler: SamplerOptions::default(),
+        };
+        if let Some(texture) = self.get(&key) {
+            return texture;
+        }
+
+        let texture = Texture::from_color(device, queue, color, kind, &key.path);
+        self.insert(key, texture)
+    }
+
+    /// White, for materials that only have a color.
+    pub fn white(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Arc<Texture> {
+        self.from_color(device, queue, [255; 4], TextureKind::Color)
+    }
+
+    /// Normal map that leaves the surface normal untouched.
+    pub fn flat_normal(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Arc<Texture> {
+        self.from_color(device, queue, [128, 128, 255, 255], TextureKind::Normal)
+    }
+
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty()
+    }
+
+    /// Bytes of GPU memory taken up by the cached textures.
+    pub fn memory_usage(&self) -> u64 {
+        self.textures.values().map(|t| t.memory_size()).sum()
+    }
+
+    /// Drops the textures nothing outside the cache holds on to any more,
+    /// returning how many bytes of GPU memory that frees.
+    pub fn evict_unused(&mut self) -> u64 {
+        let mut freed = 0;
+        self.textures.retain(|_, texture| {
+            let used = Arc::strong_count(texture) > 1;
+            if !used {
+                freed += texture.memory_size();
+            }
+            used
+        });
+        freed
+    }
+}
@@ -0,0 +1,369 @@
+use std::io::Read;
+
+use crate::{
+    error::{Error, Result},
+    mipmap,
+};
+
+/// A texture read from a KTX2 or DDS file, in the format it was saved in.
+#[derive(Debug, Clone)]
+pub struct ContainerImage {
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Texels of each mip level, largest first.
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl ContainerImage {
+    /// Width and height in pixels of mip level `level`.
+    pub fn level_size(&self, level: usize) -> (u32, u32) {
+        let halve = |size: u32| size.checked_shr(level as u32).unwrap_or(0).max(1);
+        (halve(self.width), halve(self.height))
+    }
+}
+
+const KTX2_MAGIC: &[u8] = &[
+    0xab, b'K', b'T', b'X', b' ', b'2', b'0', 0xbb, b'\r', b'\n', 0x1a, b'\n',
+];
+const DDS_MAGIC: &[u8] = b"DDS ";
+
+/// Whether `bytes` hold a KTX2 or DDS file rather than an image.
+pub fn is_container(bytes: &[u8]) -> bool {
+    bytes.starts_with(KTX2_MAGIC) || bytes.starts_with(DDS_MAGIC)
+}
+
+/// Reads the KTX2 or DDS file in `bytes`, read from `path`. Only plain 2D
+/// textures are supported, not arrays, cube maps or volumes.
+pub fn parse(path: &str, bytes: &[u8]) -> Result<ContainerImage> {
+    let image = if bytes.starts_with(KTX2_MAGIC) {
+        parse_ktx2(bytes)
+    } else if bytes.starts_with(DDS_MAGIC) {
+        parse_dds(bytes)
+    } else {
+        Err("not a KTX2 or DDS file".to_string())
+    };
+
+    image.map_err(|reason| Error::TextureContainer {
+        path: path.to_string(),
+        reason,
+    })
+}
+
+fn parse_ktx2(bytes: &[u8]) -> Result<ContainerImage, String> {
+    let reader = ktx2::Reader::new(bytes).map_err(|e| e.to_string())?;
+    let header = reader.header();
+    if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1 {
+        return Err("only 2D textures are supported".to_string());
+    }
+
+    let format = header
+        .format
+        .ok_or("Basis Universal textures aren't supported")?;
+    let format = ktx2_format(format).ok_or_else(|| format!("unsupported format {:?}", format))?;
+
+    let levels = reader
+        .levels()
+        .map(|level| match header.supercompression_scheme {
+            None => Ok(level.to_vec()),
+            Some(ktx2::SupercompressionScheme::Zstandard) => {
+                let mut decoder =
+                    ruzstd::StreamingDecoder::new(level).map_err(|e| format!("{:?}", e))?;
+                let mut data = Vec::new();
+                decoder.read_to_end(&mut data).map_err(|e| e.to_string())?;
+                Ok(data)
+            }
+            Some(scheme) => Err(format!("unsupported supercompression {:?}", scheme)),
+        })
+        .collect::<Result<_, String>>()?;
+
+    check_levels(ContainerImage {
+        format,
+        width: header.pixel_width,
+        height: header.pixel_height.max(1),
+        levels,
+    })
+}
+
+fn parse_dds(bytes: &[u8]) -> Result<ContainerImage, String> {
+    let dds = ddsfile::Dds::read(bytes).map_err(|e| e.to_string())?;
+    if dds.get_depth() > 1 || dds.get_num_array_layers() > 1 {
+        return Err("only 2D textures are supported".to_string());
+    }
+
+    let format = dds_format(&dds).ok_or("unsupported format")?;
+    let mut image = ContainerImage {
+        format,
+        width: dds.get_width(),
+        height: dds.get_height(),
+        levels: Vec::new(),
+    };
+
+    let level_count = dds.get_num_mipmap_levels().max(1);
+    check_level_count(&image, level_count as usize)?;
+
+    // Levels are stored one after the other, without padding.
+    let mut offset = 0;
+    for level in 0..level_count as usize {
+        let (width, height) = image.level_size(level);
+        let size = level_bytes(format, width, height);
+        let data = dds
+            .data
+            .get(offset..offset + size)
+            .ok_or("file is truncated")?;
+        image.levels.push(data.to_vec());
+        offset += size;
+    }
+
+    Ok(image)
+}
+
+/// Bytes of `format` texels in an image `width` by `height` pixels.
+fn level_bytes(format: wgpu::TextureFormat, width: u32, height: u32) -> usize {
+    let info = format.describe();
+    let (block_width, block_height) = info.block_dimensions;
+    width.div_ceil(block_width as u32) as usize
+        * height.div_ceil(block_height as u32) as usize
+        * info.block_size as usize
+}
+
+/// Rejects more mip levels than a full chain down to 1x1 has.
+fn check_level_count(image: &ContainerImage, count: usize) -> Result<(), String> {
+    let full_chain = mipmap::mip_level_count(image.width, image.height) as usize;
+    if count > full_chain {
+        return Err(format!(
+            "{} mip levels, but a {}x{} texture has at most {}",
+            count, image.width, image.height, full_chain
+        ));
+    }
+    Ok(())
+}
+
+fn check_levels(image: ContainerImage) -> Result<ContainerImage, String> {
+    check_level_count(&image, image.levels.len())?;
+    for (level, data) in image.levels.iter().enumerate() {
+        let (width, height) = image.level_size(level);
+        if data.len() < level_bytes(image.format, width, height) {
+            return Err(format!("mip level {} is truncated", level));
+        }
+    }
+    if image.levels.is_empty() {
+        return Err("no mip levels".to_string());
+    }
+    Ok(image)
+}
+
+fn ktx2_format(format: ktx2::Format) -> Option<wgpu::TextureFormat> {
+    use ktx2::Format as K;
+    use wgpu::{AstcBlock, AstcChannel, TextureFormat as F};
+
+    const ASTC: [(ktx2::Format, ktx2::Format, AstcBlock); 14] = [
+        (
+            K::ASTC_4x4_UNORM_BLOCK,
+            K::ASTC_4x4_SRGB_BLOCK,
+            AstcBlock::B4x4,
+        ),
+        (
+            K::ASTC_5x4_UNORM_BLOCK,
+            K::ASTC_5x4_SRGB_BLOCK,
+            AstcBlock::B5x4,
+        ),
+        (
+            K::ASTC_5x5_UNORM_BLOCK,
+            K::ASTC_5x5_SRGB_BLOCK,
+            AstcBlock::B5x5,
+        ),
+        (
+            K::ASTC_6x5_UNORM_BLOCK,
+            K::ASTC_6x5_SRGB_BLOCK,
+            AstcBlock::B6x5,
+        ),
+        (
+            K::ASTC_6x6_UNORM_BLOCK,
+            K::ASTC_6x6_SRGB_BLOCK,
+            AstcBlock::B6x6,
+        ),
+        (
+            K::ASTC_8x5_UNORM_BLOCK,
+            K::ASTC_8x5_SRGB_BLOCK,
+            AstcBlock::B8x5,
+        ),
+        (
+            K::ASTC_8x6_UNORM_BLOCK,
+            K::ASTC_8x6_SRGB_BLOCK,
+            AstcBlock::B8x6,
+        ),
+        (
+            K::ASTC_8x8_UNORM_BLOCK,
+            K::ASTC_8x8_SRGB_BLOCK,
+            AstcBlock::B8x8,
+        ),
+        (
+            K::ASTC_10x5_UNORM_BLOCK,
+            K::ASTC_10x5_SRGB_BLOCK,
+            AstcBlock::B10x5,
+        ),
+        (
+            K::ASTC_10x6_UNORM_BLOCK,
+            K::ASTC_10x6_SRGB_BLOCK,
+            AstcBlock::B10x6,
+        ),
+        (
+            K::ASTC_10x8_UNORM_BLOCK,
+            K::ASTC_10x8_SRGB_BLOCK,
+            AstcBlock::B10x8,
+        ),
+        (
+            K::ASTC_10x10_UNORM_BLOCK,
+            K::ASTC_10x10_SRGB_BLOCK,
+            AstcBlock::B10x10,
+        ),
+        (
+            K::ASTC_12x10_UNORM_BLOCK,
+            K::ASTC_12x10_SRGB_BLOCK,
+            AstcBlock::B12x10,
+        ),
+        (
+            K::ASTC_12x12_UNORM_BLOCK,
+            K::ASTC_12x12_SRGB_BLOCK,
+            AstcBlock::B12x12,
+        ),
+    ];
+    for (unorm, srgb, block) in ASTC {
+        if format == unorm || format == srgb {
+            let channel = if format == srgb {
+                AstcChannel::UnormSrgb
+            } else {
+                AstcChannel::Unorm
+            };
+            return Some(F::Astc { block, channel });
+        }
+    }
+
+    Some(match format {
+        K::R8G8B8A8_UNORM => F::Rgba8Unorm,
+        K::R8G8B8A8_SRGB => F::Rgba8UnormSrgb,
+        K::B8G8R8A8_UNORM => F::Bgra8Unorm,
+        K::B8G8R8A8_SRGB => F::Bgra8UnormSrgb,
+        K::R16G16B16A16_SFLOAT => F::Rgba16Float,
+        K::R32G32B32A32_SFLOAT => F::Rgba32Float,
+        // wgpu has no BC1 without alpha, which only differs in what the
+        // transparent index decodes to.
+        K::BC1_RGB_UNORM_BLOCK | K::BC1_RGBA_UNORM_BLOCK => F::Bc1RgbaUnorm,
+        K::BC1_RGB_SRGB_BLOCK | K::BC1_RGBA_SRGB_BLOCK => F::Bc1RgbaUnormSrgb,
+        K::BC2_UNORM_BLOCK => F::Bc2RgbaUnorm,
+        K::BC2_SRGB_BLOCK => F::Bc2RgbaUnormSrgb,
+        K::BC3_UNORM_BLOCK => F::Bc3RgbaUnorm,
+        K::BC3_SRGB_BLOCK => F::Bc3RgbaUnormSrgb,
+        K::BC4_UNORM_BLOCK => F::Bc4RUnorm,
+        K::BC4_SNORM_BLOCK => F::Bc4RSnorm,
+        K::BC5_UNORM_BLOCK => F::Bc5RgUnorm,
+        K::BC5_SNORM_BLOCK => F::Bc5RgSnorm,
+        K::BC6H_UFLOAT_BLOCK => F::Bc6hRgbUfloat,
+        K::BC6H_SFLOAT_BLOCK => F::Bc6hRgbSfloat,
+        K::BC7_UNORM_BLOCK => F::Bc7RgbaUnorm,
+        K::BC7_SRGB_BLOCK => F::Bc7RgbaUnormSrgb,
+        K::ETC2_R8G8B8_UNORM_BLOCK => F::Etc2Rgb8Unorm,
+        K::ETC2_R8G8B8_SRGB_BLOCK => F::Etc2Rgb8UnormSrgb,
+        K::ETC2_R8G8B8A1_UNORM_BLOCK => F::Etc2Rgb8A1Unorm,
+        K::ETC2_R8G8B8A1_SRGB_BLOCK => F::Etc2Rgb8A1UnormSrgb,
+        K::ETC2_R8G8B8A8_UNORM_BLOCK => F::Etc2Rgba8Unorm,
+        K::ETC2_R8G8B8A8_SRGB_BLOCK => F::Etc2Rgba8UnormSrgb,
+        K::EAC_R11_UNORM_BLOCK => F::EacR11Unorm,
+        K::EAC_R11_SNORM_BLOCK => F::EacR11Snorm,
+        K::EAC_R11G11_UNORM_BLOCK => F::EacRg11Unorm,
+        K::EAC_R11G11_SNORM_BLOCK => F::EacRg11Snorm,
+        _ => return None,
+    })
+}
+
+fn dds_format(dds: &ddsfile::Dds) -> Option<wgpu::TextureFormat> {
+    use ddsfile::{D3DFormat as D, DxgiFormat as X, FourCC};
+    use wgpu::TextureFormat as F;
+
+    if let Some(format) = dds.get_dxgi_format() {
+        return Some(match format {
+            X::R8G8B8A8_UNorm => F::Rgba8Unorm,
+            X::R8G8B8A8_UNorm_sRGB => F::Rgba8UnormSrgb,
+            X::B8G8R8A8_UNorm => F::Bgra8Unorm,
+            X::B8G8R8A8_UNorm_sRGB => F::Bgra8UnormSrgb,
+            X::R16G16B16A16_Float => F::Rgba16Float,
+            X::R32G32B32A32_Float => F::Rgba32Float,
+            X::BC1_UNorm => F::Bc1RgbaUnorm,
+            X::BC1_UNorm_sRGB => F::Bc1RgbaUnormSrgb,
+            X::BC2_UNorm => F::Bc2RgbaUnorm,
+            X::BC2_UNorm_sRGB => F::Bc2RgbaUnormSrgb,
+            X::BC3_UNorm => F::Bc3RgbaUnorm,
+            X::BC3_UNorm_sRGB => F::Bc3RgbaUnormSrgb,
+            X::BC4_UNorm => F::Bc4RUnorm,
+            X::BC4_SNorm => F::Bc4RSnorm,
+            X::BC5_UNorm => F::Bc5RgUnorm,
+            X::BC5_SNorm => F::Bc5RgSnorm,
+            X::BC6H_UF16 => F::Bc6hRgbUfloat,
+            X::BC6H_SF16 => F::Bc6hRgbSfloat,
+            X::BC7_UNorm => F::Bc7RgbaUnorm,
+            X::BC7_UNorm_sRGB => F::Bc7RgbaUnormSrgb,
+            _ => return None,
+        });
+    }
+
+    // BC4 and BC5 predate the DX10 header, and have FourCCs `D3DFormat`
+    // doesn't know about.
+    match dds.header.spf.fourcc.as_ref().map(|fourcc| fourcc.0) {
+        Some(FourCC::ATI1 | FourCC::BC4_UNORM) => return Some(F::Bc4RUnorm),
+        Some(FourCC::BC4_SNORM) => return Some(F::Bc4RSnorm),
+        Some(FourCC::ATI2) => return Some(F::Bc5RgUnorm),
+        Some(FourCC::BC5_SNORM) => return Some(F::Bc5RgSnorm),
+        _ => {}
+    }
+
+    Some(match dds.get_d3d_format()? {
+        D::DXT1 => F::Bc1RgbaUnorm,
+        D::DXT2 | D::DXT3 => F::Bc2RgbaUnorm,
+        D::DXT4 | D::DXT5 => F::Bc3RgbaUnorm,
+        D::A8B8G8R8 => F::Rgba8Unorm,
+        D::A8R8G8B8 => F::Bgra8Unorm,
+        D::A16B16G16R16F => F::Rgba16Float,
+        D::A32B32G32R32F => F::Rgba32Float,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32, height: u32, level_count: usize) -> ContainerImage {
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        ContainerImage {
+            format,
+            width,
+            height,
+            levels: (0..level_count)
+                .map(|level| {
+                    let (width, height) = (width >> level, height >> level);
+                    vec![0; level_bytes(format, width.max(1), height.max(1))]
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn full_mip_chains_are_accepted() {
+        assert!(check_levels(image(8, 2, 4)).is_ok());
+        assert!(check_levels(image(1, 1, 1)).is_ok());
+    }
+
+    #[test]
+    fn levels_beyond_1x1_are_rejected() {
+        assert!(check_levels(image(8, 2, 5)).is_err());
+        assert!(check_levels(image(1, 1, 2)).is_err());
+    }
+
+    #[test]
+    fn level_size_stops_at_1x1() {
+        let image = image(8, 2, 1);
+        assert_eq!(image.level_size(2), (2, 1));
+        assert_eq!(image.level_size(40), (1, 1));
+    }
+}
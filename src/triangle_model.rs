@@ -1,6 +1,10 @@
 use wgpu::util::DeviceExt;
 
-use crate::texture::Texture;
+use crate::{
+    assets::Assets,
+    error::Result,
+    texture::{Texture, TextureKind},
+};
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
@@ -38,7 +42,7 @@ pub struct Triangle {
 }
 
 impl Triangle {
-    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, assets: &Assets) -> Result<Self> {
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("triangle vertex buffer"),
             contents: bytemuck::cast_slice(Self::VERTICES),
@@ -52,14 +56,14 @@ impl Triangle {
             usage: wgpu::BufferUsages::INDEX,
         });
 
-        let texture = Texture::new(device, queue, "Asura.png");
+        let texture = Texture::new(device, queue, assets, "Asura.png", TextureKind::Color, None)?;
 
-        Self {
+        Ok(Self {
             vertex_buffer,
             num_size,
             index_buffer,
             texture,
-        }
+        })
     }
 
 
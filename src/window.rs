@@ -1,53 +1,59 @@
 use winit::{
     event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
+    window::{Window, WindowBuilder},
 };
 
-use crate::swapchain::State;
+use crate::{clock::FrameClock, settings::Settings, swapchain::State};
 
-pub async fn run() {
+pub async fn run(settings: Settings) {
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
         .with_title("Hello triangle 23")
         .build(&event_loop)
         .unwrap();
 
-    let mut state = State::new(window).await;
+    let mut state = match State::new(window, &settings).await {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("failed to start renderer: {}", e);
+            return;
+        }
+    };
+
+    let mut clock = FrameClock::new();
 
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent {
             window_id,
             ref event,
-        } if window_id == state.window.id() => {
-            if !state.input(event) {
-                match event {
-                    WindowEvent::CloseRequested
-                    | WindowEvent::KeyboardInput {
-                        input:
-                            KeyboardInput {
-                                state: ElementState::Pressed,
-                                virtual_keycode: Some(VirtualKeyCode::Escape),
-                                ..
-                            },
-                        ..
-                    } => *control_flow = ControlFlow::Exit,
-
-                    WindowEvent::Resized(phys_size) => {
-                        state.resize(*phys_size);
-                    }
+        } if state.window().map(Window::id) == Some(window_id) && !state.input(event) => {
+            match event {
+                WindowEvent::CloseRequested
+                | WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::Escape),
+                            ..
+                        },
+                    ..
+                } => *control_flow = ControlFlow::Exit,
 
-                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                        state.resize(**new_inner_size);
-                    }
+                WindowEvent::Resized(phys_size) => {
+                    state.resize(*phys_size);
+                }
 
-                    _ => {}
+                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                    state.resize(**new_inner_size);
                 }
+
+                _ => {}
             }
         }
 
-        Event::RedrawRequested(window_id) if window_id == state.window.id() => {
-            state.update();
+        Event::RedrawRequested(window_id) if state.window().map(Window::id) == Some(window_id) => {
+            state.update(clock.tick());
             match state.render() {
                 Ok(_) => {}
                 Err(wgpu::SurfaceError::Lost) => state.resize(state.size),
@@ -57,7 +63,9 @@ pub async fn run() {
         }
 
         Event::MainEventsCleared => {
-            state.window.request_redraw();
+            if let Some(window) = state.window() {
+                window.request_redraw();
+            }
         }
         _ => {}
     });
@@ -0,0 +1,48 @@
+use hello_wgpu::{clock::FrameTime, error::Error, settings::Settings, swapchain::State};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+
+/// The default clear color after the sRGB encode of the offscreen target.
+fn clear_pixel(settings: &Settings) -> [u8; 3] {
+    let encode = |c: f64| {
+        let c = if c <= 0.003_130_8 {
+            c * 12.92
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        };
+        (c * 255.0).round() as u8
+    };
+    let color = settings.clear_color;
+    [encode(color.r), encode(color.g), encode(color.b)]
+}
+
+#[test]
+fn headless_frame_has_requested_size_and_geometry() {
+    let settings = Settings {
+        ibl_cache: None,
+        ..Settings::default()
+    };
+    let mut state = match pollster::block_on(State::new_headless(WIDTH, HEIGHT, &settings)) {
+        Ok(state) => state,
+        Err(e @ (Error::AdapterNotFound(_) | Error::RequestDevice(_))) => {
+            eprintln!("skipping headless render test: {}", e);
+            return;
+        }
+        Err(e) => panic!("failed to create headless state: {}", e),
+    };
+
+    state.update(FrameTime::default());
+    state.render().expect("render failed");
+    let frame = state.read_frame().expect("readback failed");
+
+    assert_eq!(frame.dimensions(), (WIDTH, HEIGHT));
+    let clear = clear_pixel(&settings);
+    let drawn = frame.pixels().any(|pixel| {
+        pixel.0[..3]
+            .iter()
+            .zip(clear)
+            .any(|(&a, b)| a.abs_diff(b) > 2)
+    });
+    assert!(drawn, "every pixel matches the clear color");
+}